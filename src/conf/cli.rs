@@ -18,6 +18,10 @@ pub struct Cli {
     pub mountsource: Option<String>,
     #[arg(short = 'p', long = "partitions", value_delimiter = ',')]
     pub partitions: Vec<String>,
+    #[arg(long = "pid")]
+    pub pid: Option<i32>,
+    #[arg(long = "only-module")]
+    pub only_module: Option<String>,
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
@@ -44,4 +48,47 @@ pub enum Commands {
     Modules,
     Conflicts,
     Diagnostics,
+    Capabilities {
+        #[arg(long)]
+        probe: bool,
+    },
+    Status,
+    #[command(name = "magic-tree")]
+    MagicTree {
+        #[arg(long)]
+        path: Option<PathBuf>,
+        #[arg(long)]
+        json: bool,
+    },
+    Rw {
+        #[command(subcommand)]
+        action: RwCommands,
+    },
+    Enter {
+        #[arg(long)]
+        module: String,
+        #[arg(last = true)]
+        command: Vec<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RwCommands {
+    Enable {
+        partition: String,
+    },
+    Status,
+    Diff {
+        partition: String,
+    },
+    Reset {
+        partition: String,
+        #[arg(long)]
+        force: bool,
+    },
+    Export {
+        #[arg(short = 'o', long = "output")]
+        output: PathBuf,
+        partition: Option<String>,
+    },
 }
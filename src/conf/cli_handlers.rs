@@ -1,15 +1,25 @@
-use std::path::Path;
+use std::{os::unix::process::CommandExt, path::Path, process::Command};
 
 use anyhow::{Context, Result};
 use serde::Serialize;
 
 use crate::{
     conf::{
-        cli::Cli,
+        cli::{Cli, RwCommands},
         config::{self, Config},
     },
-    core::{inventory, inventory::model as modules, ops::planner},
-    defs, utils,
+    core::{
+        inventory,
+        inventory::{attrs, model as modules},
+        ops::planner,
+        rw,
+        state::RuntimeState,
+        status,
+    },
+    defs,
+    mount::magic_mount,
+    sys::{capabilities, namespace, partitions},
+    utils,
 };
 
 #[derive(Serialize)]
@@ -144,7 +154,7 @@ pub fn handle_diagnostics(cli: &Cli) -> Result<()> {
 
     let report = plan.analyze();
 
-    let json_issues: Vec<DiagnosticIssueJson> = report
+    let mut json_issues: Vec<DiagnosticIssueJson> = report
         .diagnostics
         .into_iter()
         .map(|i| DiagnosticIssueJson {
@@ -157,6 +167,51 @@ pub fn handle_diagnostics(cli: &Cli) -> Result<()> {
         })
         .collect();
 
+    json_issues.extend(
+        partitions::resolve(&config.partitions)
+            .into_iter()
+            .filter(|p| p.exists())
+            .map(|p| DiagnosticIssueJson {
+                level: "Info".to_string(),
+                context: format!("partition:{}", p.name),
+                message: format!(
+                    "{} resolved from {} source{}",
+                    p.root.display(),
+                    format!("{:?}", p.source).to_lowercase(),
+                    if p.sensitive {
+                        ", split per directory"
+                    } else {
+                        ""
+                    }
+                ),
+            }),
+    );
+
+    for module in &module_list {
+        json_issues.extend(
+            attrs::validate(&module.source_path)
+                .into_iter()
+                .map(|error| DiagnosticIssueJson {
+                    level: "Critical".to_string(),
+                    context: module.id.clone(),
+                    message: format!("Invalid attribute override: {}", error),
+                }),
+        );
+    }
+
+    if let Ok(state) = RuntimeState::load() {
+        json_issues.extend(state.verification.mismatches.into_iter().map(|m| {
+            DiagnosticIssueJson {
+                level: "Critical".to_string(),
+                context: m.module,
+                message: format!(
+                    "Mount verification failed for {}: expected {:?}, found {}",
+                    m.path, m.expected, m.actual
+                ),
+            }
+        }));
+    }
+
     let json =
         serde_json::to_string(&json_issues).context("Failed to serialize diagnostics report")?;
 
@@ -164,3 +219,113 @@ pub fn handle_diagnostics(cli: &Cli) -> Result<()> {
 
     Ok(())
 }
+
+pub fn handle_status(cli: &Cli) -> Result<()> {
+    let config = load_config(cli)?;
+    let state = RuntimeState::load().context("Failed to load runtime state")?;
+
+    let report = status::check(&state, &config.mountsource, &config.partitions)?;
+
+    let json = serde_json::to_string(&report).context("Failed to serialize status report")?;
+
+    println!("{}", json);
+
+    if report.drift {
+        anyhow::bail!("Mounts drifted from the recorded runtime state");
+    }
+
+    Ok(())
+}
+
+pub fn handle_magic_tree(path: Option<&Path>, json: bool) -> Result<()> {
+    let tree = magic_mount::tree::load()?;
+
+    let node = match path {
+        Some(prefix) => tree
+            .find(prefix)
+            .with_context(|| format!("{} is not part of the magic mount tree", prefix.display()))?,
+        None => &tree,
+    };
+
+    if json {
+        let json = serde_json::to_string(node).context("Failed to serialize magic mount tree")?;
+        println!("{}", json);
+    } else {
+        print!("{}", node);
+    }
+
+    Ok(())
+}
+
+pub fn handle_capabilities(probe: bool) -> Result<()> {
+    let json = if probe {
+        serde_json::to_string(capabilities::probe())
+    } else {
+        if !Path::new(defs::STATE_FILE).exists() {
+            anyhow::bail!("No capabilities recorded since boot, pass --probe to probe now");
+        }
+        let state = RuntimeState::load().context("Failed to load runtime state")?;
+        serde_json::to_string(&state.capabilities)
+    }
+    .context("Failed to serialize kernel capabilities")?;
+
+    println!("{}", json);
+
+    Ok(())
+}
+
+pub fn handle_rw(cli: &Cli, action: &RwCommands) -> Result<()> {
+    match action {
+        RwCommands::Enable { partition } => {
+            let config = load_config(cli)?;
+            rw::enable(&config, partition)?;
+            println!(
+                "Writable overlay enabled for {}, effective on next boot.",
+                partition
+            );
+        }
+        RwCommands::Status => {
+            let status = rw::status().context("Failed to inspect writable overlays")?;
+            let json = serde_json::to_string(&status)
+                .context("Failed to serialize writable overlay status")?;
+            println!("{}", json);
+        }
+        RwCommands::Diff { partition } => {
+            let changes = rw::diff(partition)?;
+            let json = serde_json::to_string(&changes)
+                .context("Failed to serialize writable overlay diff")?;
+            println!("{}", json);
+        }
+        RwCommands::Reset { partition, force } => {
+            rw::reset(partition, *force)?;
+            println!("Writable overlay reset for {}.", partition);
+        }
+        RwCommands::Export { output, partition } => {
+            rw::export(output, partition.as_deref())?;
+            println!("Writable overlays exported to {}.", output.display());
+        }
+    }
+
+    Ok(())
+}
+
+pub fn handle_enter(module_id: &str, command: &[String]) -> Result<()> {
+    let pin = namespace::pin_path(module_id)?;
+    if !crate::sys::mount::is_mounted(&pin) {
+        anyhow::bail!("Module {} has no isolated namespace", module_id);
+    }
+
+    namespace::enter(&pin)?;
+
+    let (program, args) = match command.split_first() {
+        Some((program, args)) => (program.clone(), args),
+        None => (
+            std::env::var("SHELL").unwrap_or_else(|_| "/system/bin/sh".to_string()),
+            &[][..],
+        ),
+    };
+
+    let err = Command::new(&program).args(args).exec();
+
+    Err(err).with_context(|| format!("Failed to execute {}", program))
+}
@@ -18,6 +18,15 @@ pub enum OverlayMode {
     Erofs,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum VerifyMode {
+    Off,
+    #[default]
+    Sample,
+    Full,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum DefaultMode {
@@ -41,6 +50,8 @@ pub struct ModuleRules {
     pub default_mode: MountMode,
     #[serde(default)]
     pub paths: HashMap<String, MountMode>,
+    #[serde(default)]
+    pub targets: HashMap<String, String>,
 }
 
 impl ModuleRules {
@@ -52,6 +63,85 @@ impl ModuleRules {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct OverlayOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redirect_dir: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metacopy: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub xino: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub userxattr: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub override_creds: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volatile: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nfs_export: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<String>,
+}
+
+impl OverlayOptions {
+    pub fn merged_with(&self, other: &OverlayOptions) -> OverlayOptions {
+        OverlayOptions {
+            redirect_dir: other.redirect_dir.clone().or(self.redirect_dir.clone()),
+            metacopy: other.metacopy.or(self.metacopy),
+            index: other.index.or(self.index),
+            xino: other.xino.clone().or(self.xino.clone()),
+            userxattr: other.userxattr.or(self.userxattr),
+            override_creds: other.override_creds.or(self.override_creds),
+            volatile: other.volatile.or(self.volatile),
+            nfs_export: other.nfs_export.or(self.nfs_export),
+            uuid: other.uuid.clone().or(self.uuid.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct HardeningPolicy {
+    pub enabled: bool,
+    pub read_only: bool,
+    pub nosuid: bool,
+    pub nodev: bool,
+    pub noexec: bool,
+    pub nosymfollow: bool,
+    pub setuid_targets: Vec<String>,
+    pub noexec_dirs: Vec<String>,
+}
+
+impl Default for HardeningPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            read_only: true,
+            nosuid: false,
+            nodev: false,
+            noexec: false,
+            nosymfollow: false,
+            setuid_targets: [
+                "/system/bin",
+                "/system/xbin",
+                "/system_ext/bin",
+                "/vendor/bin",
+                "/product/bin",
+                "/odm/bin",
+            ]
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+            noexec_dirs: ["etc", "fonts", "media", "usr"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     #[serde(default = "default_moduledir")]
@@ -70,6 +160,20 @@ pub struct Config {
     pub default_mode: DefaultMode,
     #[serde(default)]
     pub rules: HashMap<String, ModuleRules>,
+    #[serde(default)]
+    pub overlay_options: OverlayOptions,
+    #[serde(default)]
+    pub partition_overlay_options: HashMap<String, OverlayOptions>,
+    #[serde(default)]
+    pub force_serial_mount: bool,
+    #[serde(default)]
+    pub hardening: HardeningPolicy,
+    #[serde(default)]
+    pub isolated_modules: Vec<String>,
+    #[serde(default)]
+    pub verify_mode: VerifyMode,
+    #[serde(default)]
+    pub allowed_target_prefixes: Vec<String>,
 }
 
 fn default_moduledir() -> PathBuf {
@@ -112,6 +216,13 @@ impl Default for Config {
             allow_umount_coexistence: false,
             default_mode: DefaultMode::default(),
             rules: HashMap::new(),
+            overlay_options: OverlayOptions::default(),
+            partition_overlay_options: HashMap::new(),
+            force_serial_mount: false,
+            hardening: HardeningPolicy::default(),
+            isolated_modules: Vec::new(),
+            verify_mode: VerifyMode::default(),
+            allowed_target_prefixes: Vec::new(),
         }
     }
 }
@@ -141,6 +252,13 @@ impl Config {
         Ok(())
     }
 
+    pub fn overlay_options_for(&self, partition: &str) -> OverlayOptions {
+        match self.partition_overlay_options.get(partition) {
+            Some(options) => self.overlay_options.merged_with(options),
+            None => self.overlay_options.clone(),
+        }
+    }
+
     pub fn merge_with_cli(
         &mut self,
         moduledir: Option<PathBuf>,
@@ -1,22 +1,40 @@
-use std::{collections::HashSet, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
 
 use anyhow::Result;
 
-use crate::{conf::config::Config, core::ops::planner::OverlayOperation};
+use crate::{
+    conf::config::Config, core::ops::planner::OverlayOperation,
+    mount::magic_mount::MagicMountReport,
+};
 
 pub trait StorageBackend: Send + Sync {
     fn commit(&mut self, disable_umount: bool) -> Result<()>;
     fn mount_point(&self) -> &Path;
     fn mode(&self) -> &str;
+    fn report_usage(&self) {}
+}
+
+pub struct OverlayMountResult {
+    pub module_ids: Vec<String>,
+    pub writable: bool,
+}
+
+pub struct MagicMountResult {
+    pub module_ids: Vec<String>,
+    pub report: MagicMountReport,
 }
 
 pub trait MountDriver: Send + Sync {
     fn is_supported(&self) -> Result<bool>;
-    fn mount_overlay(&self, op: &OverlayOperation, config: &Config) -> Result<Vec<String>>;
+    fn mount_overlay(&self, op: &OverlayOperation, config: &Config) -> Result<OverlayMountResult>;
     fn mount_magic(
         &self,
         ids: &HashSet<String>,
+        scopes: &HashMap<String, Vec<PathBuf>>,
         config: &Config,
         tempdir: &Path,
-    ) -> Result<Vec<String>>;
+    ) -> Result<MagicMountResult>;
 }
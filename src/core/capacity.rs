@@ -0,0 +1,188 @@
+use std::{
+    collections::HashMap,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use extattr::{lgetxattr, llistxattr};
+use walkdir::WalkDir;
+
+use crate::core::inventory::Module;
+
+const EXT4_RESERVED_INODES: u64 = 11;
+const EXT4_BLOCKS_PER_GROUP_1K: u64 = 8192;
+const EXT4_GROUP_OVERHEAD_BLOCKS: u64 = 3;
+const DIRENT_HEADER_SIZE: u64 = 8;
+const XATTR_ENTRY_HEADER_SIZE: u64 = 16;
+const HEADROOM_PERCENT: u64 = 10;
+const MIN_IMAGE_SIZE: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+pub struct FsParams {
+    pub name: &'static str,
+    pub block_size: u64,
+    pub inode_size: u64,
+    pub inline_xattr_size: u64,
+    pub inline_symlink_size: u64,
+}
+
+impl FsParams {
+    pub const EXT4: Self = Self {
+        name: "ext4",
+        block_size: 1024,
+        inode_size: 256,
+        inline_xattr_size: 96,
+        inline_symlink_size: 59,
+    };
+
+    fn blocks_for(&self, bytes: u64) -> u64 {
+        bytes.div_ceil(self.block_size)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Estimate {
+    pub data_blocks: u64,
+    pub dir_blocks: u64,
+    pub symlink_blocks: u64,
+    pub xattr_blocks: u64,
+    pub inodes: u64,
+    pub files: u64,
+    pub dirs: u64,
+    pub symlinks: u64,
+}
+
+impl Estimate {
+    pub fn content_blocks(&self) -> u64 {
+        self.data_blocks + self.dir_blocks + self.symlink_blocks + self.xattr_blocks
+    }
+}
+
+fn xattr_bytes(path: &Path) -> u64 {
+    let Ok(names) = llistxattr(path) else {
+        return 0;
+    };
+
+    names
+        .iter()
+        .map(|name| {
+            let value_len = lgetxattr(path, name).map(|v| v.len() as u64).unwrap_or(0);
+            (XATTR_ENTRY_HEADER_SIZE + name.len() as u64 + value_len).next_multiple_of(4)
+        })
+        .sum()
+}
+
+pub fn estimate(modules: &[Module], params: FsParams) -> Estimate {
+    let mut est = Estimate::default();
+    let mut dir_entry_bytes: HashMap<PathBuf, u64> = HashMap::new();
+
+    for module in modules {
+        for entry in WalkDir::new(&module.source_path).into_iter().flatten() {
+            let Ok(metadata) = entry.path().symlink_metadata() else {
+                continue;
+            };
+            let file_type = metadata.file_type();
+
+            est.inodes += 1;
+
+            if let Some(parent) = entry.path().parent() {
+                let name_len = entry.file_name().len() as u64;
+                *dir_entry_bytes.entry(parent.to_path_buf()).or_default() +=
+                    (DIRENT_HEADER_SIZE + name_len).next_multiple_of(4);
+            }
+
+            if file_type.is_dir() {
+                est.dirs += 1;
+                dir_entry_bytes
+                    .entry(entry.path().to_path_buf())
+                    .or_default();
+            } else if file_type.is_symlink() {
+                est.symlinks += 1;
+                if metadata.size() > params.inline_symlink_size {
+                    est.symlink_blocks += params.blocks_for(metadata.size());
+                }
+            } else if file_type.is_file() {
+                est.files += 1;
+                est.data_blocks += params.blocks_for(metadata.size());
+            }
+
+            if xattr_bytes(entry.path()) > params.inline_xattr_size {
+                est.xattr_blocks += 1;
+            }
+        }
+    }
+
+    est.dir_blocks = dir_entry_bytes
+        .values()
+        .map(|bytes| params.blocks_for(*bytes).max(1))
+        .sum();
+
+    est
+}
+
+fn ext4_journal_blocks(total_blocks: u64) -> u64 {
+    match total_blocks {
+        0..2048 => 0,
+        2048..32768 => 1024,
+        32768..262144 => 4096,
+        262144..524288 => 8192,
+        _ => 16384,
+    }
+}
+
+pub fn ext4_layout(est: &Estimate) -> (u64, u64) {
+    let params = FsParams::EXT4;
+    let inodes = (est.inodes + EXT4_RESERVED_INODES) * (100 + HEADROOM_PERCENT) / 100;
+    let inode_table_blocks = params.blocks_for(inodes * params.inode_size);
+    let content_blocks = est.content_blocks() * (100 + HEADROOM_PERCENT) / 100;
+
+    let mut blocks = content_blocks + inode_table_blocks;
+    let groups = blocks.div_ceil(EXT4_BLOCKS_PER_GROUP_1K).max(1);
+    blocks += groups * EXT4_GROUP_OVERHEAD_BLOCKS;
+    blocks += ext4_journal_blocks(blocks);
+
+    let size = (blocks * params.block_size).max(MIN_IMAGE_SIZE);
+    let inodes = inodes.max(size / (16 * 1024));
+
+    (size, inodes)
+}
+
+pub fn log_estimate(params: FsParams, est: &Estimate) {
+    log::info!(
+        "capacity estimate ({}): {} blocks ({} data, {} dir, {} symlink, {} xattr), {} inodes ({} files, {} dirs, {} symlinks)",
+        params.name,
+        est.content_blocks(),
+        est.data_blocks,
+        est.dir_blocks,
+        est.symlink_blocks,
+        est.xattr_blocks,
+        est.inodes,
+        est.files,
+        est.dirs,
+        est.symlinks
+    );
+}
+
+pub fn log_usage(params: FsParams, est: &Estimate, mount_point: &Path) {
+    match rustix::fs::statvfs(mount_point) {
+        Ok(st) => {
+            let used_bytes = (st.f_blocks - st.f_bfree) * st.f_frsize;
+            let used_inodes = st.f_files - st.f_ffree;
+            log::info!(
+                "capacity usage ({}): estimated {} bytes / {} inodes, actual {} bytes / {} inodes, free {} bytes / {} inodes",
+                params.name,
+                est.content_blocks() * params.block_size,
+                est.inodes,
+                used_bytes,
+                used_inodes,
+                st.f_bavail * st.f_frsize,
+                st.f_ffree
+            );
+        }
+        Err(e) => log::warn!(
+            "Failed to statvfs {} for capacity report: {}",
+            mount_point.display(),
+            e
+        ),
+    }
+}
@@ -0,0 +1,401 @@
+use std::{
+    fs,
+    os::unix::fs::{PermissionsExt, lchown},
+    path::Path,
+};
+
+use anyhow::{Context, Result, bail};
+use regex_lite::Regex;
+use serde::Deserialize;
+use walkdir::WalkDir;
+
+use crate::{
+    defs,
+    sys::fs::{lsetfilecon, set_file_capabilities},
+};
+
+const CAPABILITIES: &[&str] = &[
+    "CHOWN",
+    "DAC_OVERRIDE",
+    "DAC_READ_SEARCH",
+    "FOWNER",
+    "FSETID",
+    "KILL",
+    "SETGID",
+    "SETUID",
+    "SETPCAP",
+    "LINUX_IMMUTABLE",
+    "NET_BIND_SERVICE",
+    "NET_BROADCAST",
+    "NET_ADMIN",
+    "NET_RAW",
+    "IPC_LOCK",
+    "IPC_OWNER",
+    "SYS_MODULE",
+    "SYS_RAWIO",
+    "SYS_CHROOT",
+    "SYS_PTRACE",
+    "SYS_PACCT",
+    "SYS_ADMIN",
+    "SYS_BOOT",
+    "SYS_NICE",
+    "SYS_RESOURCE",
+    "SYS_TIME",
+    "SYS_TTY_CONFIG",
+    "MKNOD",
+    "LEASE",
+    "AUDIT_WRITE",
+    "AUDIT_CONTROL",
+    "SETFCAP",
+    "MAC_OVERRIDE",
+    "MAC_ADMIN",
+    "SYSLOG",
+    "WAKE_ALARM",
+    "BLOCK_SUSPEND",
+    "AUDIT_READ",
+    "PERFMON",
+    "BPF",
+    "CHECKPOINT_RESTORE",
+];
+
+const ANDROID_IDS: &[(&str, u32)] = &[
+    ("root", 0),
+    ("system", 1000),
+    ("radio", 1001),
+    ("bluetooth", 1002),
+    ("graphics", 1003),
+    ("input", 1004),
+    ("audio", 1005),
+    ("camera", 1006),
+    ("log", 1007),
+    ("wifi", 1010),
+    ("media", 1013),
+    ("nfc", 1027),
+    ("shell", 2000),
+    ("cache", 2001),
+    ("inet", 3003),
+    ("net_raw", 3004),
+    ("everybody", 9997),
+    ("nobody", 9999),
+];
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Id {
+    Numeric(u32),
+    Named(String),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawEntry {
+    path: String,
+    mode: Option<String>,
+    uid: Option<Id>,
+    gid: Option<Id>,
+    context: Option<String>,
+    capabilities: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileAttrs {
+    pub mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub context: Option<String>,
+    pub capabilities: Option<u64>,
+}
+
+impl FileAttrs {
+    fn overlay(&mut self, other: &Self) {
+        self.mode = other.mode.or(self.mode);
+        self.uid = other.uid.or(self.uid);
+        self.gid = other.gid.or(self.gid);
+        self.context = other.context.clone().or(self.context.take());
+        self.capabilities = other.capabilities.or(self.capabilities);
+    }
+}
+
+struct Rule {
+    pattern: Regex,
+    attrs: FileAttrs,
+}
+
+pub struct AttrManifest {
+    rules: Vec<Rule>,
+}
+
+fn glob_regex(glob: &str) -> Result<Regex> {
+    let glob = glob.trim_start_matches('/');
+    if glob.is_empty() {
+        bail!("empty path");
+    }
+
+    let mut pattern = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    pattern.push_str("(?:.*/)?");
+                } else {
+                    pattern.push_str(".*");
+                }
+            }
+            '*' => pattern.push_str("[^/]*"),
+            '?' => pattern.push_str("[^/]"),
+            c => pattern.push_str(&regex_lite::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+
+    Regex::new(&pattern).with_context(|| format!("invalid glob '{}'", glob))
+}
+
+fn parse_id(id: &Id) -> Result<u32> {
+    match id {
+        Id::Numeric(n) => Ok(*n),
+        Id::Named(name) => name
+            .parse()
+            .ok()
+            .or_else(|| {
+                ANDROID_IDS
+                    .iter()
+                    .find(|(n, _)| n == name)
+                    .map(|(_, id)| *id)
+            })
+            .with_context(|| format!("unknown user/group '{}'", name)),
+    }
+}
+
+fn parse_capabilities(names: &[String]) -> Result<u64> {
+    if names.is_empty() {
+        bail!("empty capability list");
+    }
+
+    names.iter().try_fold(0u64, |caps, name| {
+        let upper = name.to_uppercase();
+        let bare = upper.strip_prefix("CAP_").unwrap_or(&upper);
+        let bit = CAPABILITIES
+            .iter()
+            .position(|c| *c == bare)
+            .with_context(|| format!("unknown capability '{}'", name))?;
+        Ok(caps | (1 << bit))
+    })
+}
+
+fn parse_entry(entry: &RawEntry) -> Result<Rule> {
+    let mode = entry
+        .mode
+        .as_deref()
+        .map(|mode| match u32::from_str_radix(mode, 8) {
+            Ok(bits) if bits <= 0o7777 => Ok(bits),
+            _ => bail!("invalid mode '{}'", mode),
+        })
+        .transpose()?;
+
+    if let Some(context) = &entry.context
+        && (context.split(':').count() < 4 || context.split(':').any(str::is_empty))
+    {
+        bail!("invalid SELinux context '{}'", context);
+    }
+
+    let attrs = FileAttrs {
+        mode,
+        uid: entry.uid.as_ref().map(parse_id).transpose()?,
+        gid: entry.gid.as_ref().map(parse_id).transpose()?,
+        context: entry.context.clone(),
+        capabilities: entry
+            .capabilities
+            .as_deref()
+            .map(parse_capabilities)
+            .transpose()?,
+    };
+    if attrs == FileAttrs::default() {
+        bail!("no attributes declared");
+    }
+
+    Ok(Rule {
+        pattern: glob_regex(&entry.path)?,
+        attrs,
+    })
+}
+
+fn parse(content: &str) -> Result<(Vec<Rule>, Vec<String>)> {
+    let entries: Vec<RawEntry> = serde_json::from_str(content)?;
+    let mut rules = Vec::new();
+    let mut errors = Vec::new();
+
+    for entry in &entries {
+        match parse_entry(entry) {
+            Ok(rule) => rules.push(rule),
+            Err(e) => errors.push(format!("{}: {:#}", entry.path, e)),
+        }
+    }
+    Ok((rules, errors))
+}
+
+impl AttrManifest {
+    pub fn load(module_dir: &Path) -> Result<Option<Self>> {
+        let path = module_dir.join(defs::ATTRS_FILE_NAME);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let (rules, errors) =
+            parse(&content).with_context(|| format!("Failed to parse {}", path.display()))?;
+        for error in errors {
+            log::warn!("Ignoring entry in {}: {}", path.display(), error);
+        }
+
+        Ok(Some(Self { rules }))
+    }
+
+    pub fn lookup(&self, relative: &Path) -> Option<FileAttrs> {
+        let relative = relative.to_str()?;
+        let mut matched: Option<FileAttrs> = None;
+
+        for rule in self.rules.iter().filter(|r| r.pattern.is_match(relative)) {
+            matched.get_or_insert_default().overlay(&rule.attrs);
+        }
+        matched
+    }
+
+    pub fn apply_tree(&self, root: &Path) -> usize {
+        let mut applied = 0;
+
+        for entry in WalkDir::new(root).min_depth(1).into_iter().flatten() {
+            let Ok(relative) = entry.path().strip_prefix(root) else {
+                continue;
+            };
+            let Some(attrs) = self.lookup(relative) else {
+                continue;
+            };
+
+            match apply(entry.path(), &attrs) {
+                Ok(()) => applied += 1,
+                Err(e) => log::warn!("{:#}", e),
+            }
+        }
+        applied
+    }
+}
+
+pub fn apply(path: &Path, attrs: &FileAttrs) -> Result<()> {
+    let meta = fs::symlink_metadata(path)?;
+
+    if attrs.uid.is_some() || attrs.gid.is_some() {
+        lchown(path, attrs.uid, attrs.gid)
+            .with_context(|| format!("Failed to chown {}", path.display()))?;
+    }
+    if let Some(mode) = attrs.mode
+        && !meta.is_symlink()
+    {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))
+            .with_context(|| format!("Failed to chmod {}", path.display()))?;
+    }
+    if let Some(context) = &attrs.context {
+        lsetfilecon(path, context)?;
+    }
+    if let Some(caps) = attrs.capabilities
+        && meta.is_file()
+    {
+        set_file_capabilities(path, caps)?;
+    }
+    Ok(())
+}
+
+pub fn validate(module_dir: &Path) -> Vec<String> {
+    let path = module_dir.join(defs::ATTRS_FILE_NAME);
+    let Ok(content) = fs::read_to_string(&path) else {
+        return Vec::new();
+    };
+
+    match parse(&content) {
+        Ok((_, errors)) => errors,
+        Err(e) => vec![format!("{}: {}", defs::ATTRS_FILE_NAME, e)],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(json: &str) -> RawEntry {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn glob_regex_matches_path_segments() {
+        let re = glob_regex("/system/bin/*").unwrap();
+        assert!(re.is_match("system/bin/sh"));
+        assert!(!re.is_match("system/bin/sub/sh"));
+        assert!(!re.is_match("system/bin"));
+
+        let re = glob_regex("system/**/*.so").unwrap();
+        assert!(re.is_match("system/lib.so"));
+        assert!(re.is_match("system/lib64/hw/foo.so"));
+        assert!(!re.is_match("vendor/lib64/foo.so"));
+
+        let re = glob_regex("system/etc/**").unwrap();
+        assert!(re.is_match("system/etc/a/b"));
+
+        let re = glob_regex("system/bin/su?").unwrap();
+        assert!(re.is_match("system/bin/sux"));
+        assert!(!re.is_match("system/bin/su/"));
+    }
+
+    #[test]
+    fn glob_regex_escapes_literals_and_rejects_empty() {
+        let re = glob_regex("system/etc/a+b.conf").unwrap();
+        assert!(re.is_match("system/etc/a+b.conf"));
+        assert!(!re.is_match("system/etc/aab.conf"));
+        assert!(!re.is_match("system/etc/a+bxconf"));
+
+        assert!(glob_regex("").is_err());
+        assert!(glob_regex("/").is_err());
+    }
+
+    #[test]
+    fn parse_entry_resolves_ids_modes_and_capabilities() {
+        let rule = parse_entry(&entry(
+            r#"{"path": "system/bin/foo", "mode": "4755", "uid": "shell", "gid": 1000,
+                "context": "u:object_r:system_file:s0",
+                "capabilities": ["net_raw", "CAP_NET_ADMIN"]}"#,
+        ))
+        .unwrap();
+
+        assert!(rule.pattern.is_match("system/bin/foo"));
+        assert_eq!(
+            rule.attrs,
+            FileAttrs {
+                mode: Some(0o4755),
+                uid: Some(2000),
+                gid: Some(1000),
+                context: Some("u:object_r:system_file:s0".to_string()),
+                capabilities: Some((1 << 13) | (1 << 12)),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_entry_rejects_invalid_fields() {
+        for json in [
+            r#"{"path": "system/bin/foo"}"#,
+            r#"{"path": "system/bin/foo", "mode": "9999"}"#,
+            r#"{"path": "system/bin/foo", "mode": "17777"}"#,
+            r#"{"path": "system/bin/foo", "uid": "nobody_here"}"#,
+            r#"{"path": "system/bin/foo", "context": "u:object_r:system_file"}"#,
+            r#"{"path": "system/bin/foo", "context": "u::system_file:s0"}"#,
+            r#"{"path": "system/bin/foo", "capabilities": []}"#,
+            r#"{"path": "system/bin/foo", "capabilities": ["fly"]}"#,
+            r#"{"path": "", "mode": "0644"}"#,
+        ] {
+            assert!(parse_entry(&entry(json)).is_err(), "{json}");
+        }
+    }
+}
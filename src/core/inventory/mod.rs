@@ -1,5 +1,8 @@
+pub mod attrs;
 pub mod model;
+pub mod removals;
 pub mod scanner;
+pub mod stats;
 
 pub use scanner::*;
 
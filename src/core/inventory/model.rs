@@ -3,25 +3,27 @@ use std::{
     fs::{self},
     io::{BufRead, BufReader},
     path::Path,
-    process::Command,
     sync::{OnceLock, atomic::Ordering},
+    time::Duration,
 };
 
 use anyhow::Result;
 use regex_lite::Regex;
 use serde::Serialize;
 
-use super::scanner as inventory;
+use super::{scanner as inventory, stats};
 use crate::{
     conf::config::{self, MountMode},
     core::state::RuntimeState,
     defs,
     sys::fs::atomic_write,
-    utils::KSU,
+    utils::{self, KSU},
 };
 
 static MODULE_PROP_REGEX: OnceLock<Regex> = OnceLock::new();
 
+const KSUD_TIMEOUT: Duration = Duration::from_secs(10);
+
 #[derive(Default)]
 struct ModuleProp {
     name: String,
@@ -67,10 +69,11 @@ struct ModuleInfo {
     mode: String,
     is_mounted: bool,
     rules: config::ModuleRules,
+    stats: stats::ModuleStats,
 }
 
 impl ModuleInfo {
-    fn new(m: inventory::Module, mounted_set: &HashSet<&str>) -> Self {
+    fn new(m: inventory::Module, mounted_set: &HashSet<&str>, stats: stats::ModuleStats) -> Self {
         let prop = ModuleProp::from(m.source_path.join("module.prop").as_path());
 
         let mode_str = match m.rules.default_mode {
@@ -88,6 +91,7 @@ impl ModuleInfo {
             description: prop.description,
             mode: mode_str.to_string(),
             rules: m.rules,
+            stats,
         }
     }
 }
@@ -104,9 +108,15 @@ pub fn print_list(config: &config::Config) -> Result<()> {
         .map(|s| s.as_str())
         .collect();
 
+    let storage_root = Some(state.mount_point.as_path()).filter(|p| p.is_dir());
+    let mut module_stats = stats::collect(&modules, storage_root, config);
+
     let infos: Vec<ModuleInfo> = modules
         .into_iter()
-        .map(|m| ModuleInfo::new(m, &mounted_ids))
+        .map(|m| {
+            let stats = module_stats.remove(&m.id).unwrap_or_default();
+            ModuleInfo::new(m, &mounted_ids, stats)
+        })
         .collect();
 
     println!("{}", serde_json::to_string(&infos)?);
@@ -138,18 +148,19 @@ pub fn update_description(storage_mode: &str, overlay_count: usize, magic_count:
         mode_str, status_emoji, overlay_count, magic_count
     );
     if KSU.load(Ordering::Relaxed) {
-        let result = Command::new("ksud")
-            .arg("module")
-            .arg("config")
-            .arg("set")
-            .arg("override.description")
-            .arg(&desc_text)
-            .status();
-
-        if let Ok(status) = result
-            && status.success()
-        {
-            return;
+        match utils::run_checked(
+            "ksud",
+            [
+                "module",
+                "config",
+                "set",
+                "override.description",
+                desc_text.as_str(),
+            ],
+            KSUD_TIMEOUT,
+        ) {
+            Ok(_) => return,
+            Err(e) => log::warn!("Failed to override description via ksud: {}", e),
         }
     }
 
@@ -0,0 +1,134 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use walkdir::WalkDir;
+
+use crate::{defs, sys::partitions};
+
+pub fn marker_target<'a>(name: &'a str, meta: &fs::Metadata) -> Option<&'a str> {
+    if !meta.is_file() || meta.len() != 0 {
+        return None;
+    }
+    name.strip_suffix(defs::REMOVE_MARKER_SUFFIX)
+        .filter(|target| !target.is_empty())
+}
+
+fn parse_entry(line: &str) -> Option<PathBuf> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let path = Path::new(line.trim_start_matches('/'));
+    if path.as_os_str().is_empty()
+        || path
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+    Some(path.to_path_buf())
+}
+
+fn listed(module_dir: &Path, partitions: &[String]) -> Vec<PathBuf> {
+    let list = module_dir.join(defs::REMOVE_LIST_FILE_NAME);
+    let Ok(content) = fs::read_to_string(&list) else {
+        return Vec::new();
+    };
+
+    let mut paths = Vec::new();
+    for line in content.lines() {
+        let Some(path) = parse_entry(line) else {
+            if !line.trim().is_empty() && !line.trim().starts_with('#') {
+                log::warn!("Ignoring invalid entry '{}' in {}", line, list.display());
+            }
+            continue;
+        };
+
+        let in_partition = path
+            .iter()
+            .next()
+            .is_some_and(|p| partitions.iter().any(|name| p == name.as_str()));
+        if in_partition && path.components().count() > 1 {
+            paths.push(path);
+        } else {
+            log::warn!(
+                "Ignoring '{}' in {}: not below a partition",
+                line,
+                list.display()
+            );
+        }
+    }
+    paths
+}
+
+fn markers(module_dir: &Path, partitions: &[String]) -> Vec<PathBuf> {
+    partitions
+        .iter()
+        .map(|p| module_dir.join(p))
+        .filter(|p| p.is_dir())
+        .flat_map(|root| WalkDir::new(root).min_depth(1).into_iter().flatten())
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok()?;
+            let name = entry.file_name().to_str()?;
+            let target = marker_target(name, &meta)?;
+            let rel = entry.path().strip_prefix(module_dir).ok()?;
+            Some(rel.with_file_name(target))
+        })
+        .collect()
+}
+
+pub fn collect(module_dir: &Path, extra_partitions: &[String]) -> Vec<PathBuf> {
+    let partitions = partitions::names(extra_partitions);
+    let mut paths = listed(module_dir, &partitions);
+    paths.extend(markers(module_dir, &partitions));
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_entry_accepts_relative_and_rooted_paths() {
+        assert_eq!(
+            parse_entry("system/app/Foo"),
+            Some(PathBuf::from("system/app/Foo"))
+        );
+        assert_eq!(
+            parse_entry("  /vendor/etc/foo.conf  "),
+            Some(PathBuf::from("vendor/etc/foo.conf"))
+        );
+    }
+
+    #[test]
+    fn parse_entry_rejects_comments_blanks_and_traversal() {
+        assert_eq!(parse_entry(""), None);
+        assert_eq!(parse_entry("   "), None);
+        assert_eq!(parse_entry("# system/app/Foo"), None);
+        assert_eq!(parse_entry("system/../data"), None);
+        assert_eq!(parse_entry("/"), None);
+    }
+
+    #[test]
+    fn marker_target_needs_an_empty_regular_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let empty = dir.path().join("Foo.remove");
+        let filled = dir.path().join("Bar.remove");
+        let subdir = dir.path().join("Baz.remove");
+        fs::write(&empty, "").unwrap();
+        fs::write(&filled, "x").unwrap();
+        fs::create_dir(&subdir).unwrap();
+
+        let meta = |p: &Path| fs::symlink_metadata(p).unwrap();
+        assert_eq!(marker_target("Foo.remove", &meta(&empty)), Some("Foo"));
+        assert_eq!(marker_target("Bar.remove", &meta(&filled)), None);
+        assert_eq!(marker_target("Baz.remove", &meta(&subdir)), None);
+        assert_eq!(marker_target("Foo", &meta(&empty)), None);
+        assert_eq!(marker_target(".remove", &meta(&empty)), None);
+    }
+}
@@ -17,6 +17,7 @@ use crate::{
 struct PartialRules {
     default_mode: Option<MountMode>,
     paths: Option<HashMap<String, MountMode>>,
+    targets: Option<HashMap<String, String>>,
 }
 
 fn load_module_rules(module_dir: &Path, module_id: &str, cfg: &config::Config) -> ModuleRules {
@@ -40,6 +41,9 @@ fn load_module_rules(module_dir: &Path, module_id: &str, cfg: &config::Config) -
                     if let Some(paths) = partial.paths {
                         rules.paths = paths;
                     }
+                    if let Some(targets) = partial.targets {
+                        rules.targets = targets;
+                    }
                 }
                 Err(e) => {
                     log::warn!("Failed to parse rules for module '{}': {}", module_id, e)
@@ -52,6 +56,7 @@ fn load_module_rules(module_dir: &Path, module_id: &str, cfg: &config::Config) -
     if let Some(global_rules) = cfg.rules.get(module_id) {
         rules.default_mode = global_rules.default_mode.clone();
         rules.paths.extend(global_rules.paths.clone());
+        rules.targets.extend(global_rules.targets.clone());
     }
 
     rules
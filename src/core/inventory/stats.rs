@@ -0,0 +1,219 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+    path::Path,
+};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::{
+    conf::config,
+    core::inventory::Module,
+    defs,
+    sys::{fs::atomic_write, partitions},
+};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PartitionStats {
+    pub bytes: u64,
+    pub files: u64,
+    pub dirs: u64,
+    pub symlinks: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModuleStats {
+    pub source: BTreeMap<String, PartitionStats>,
+    pub storage: BTreeMap<String, PartitionStats>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct CacheEntry {
+    manifest: String,
+    partitions: BTreeMap<String, PartitionStats>,
+}
+
+type StatsCache = HashMap<String, CacheEntry>;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+fn load_cache() -> StatsCache {
+    fs::read_to_string(defs::MODULE_STATS_CACHE)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_cache(cache: &StatsCache) {
+    match serde_json::to_string(cache) {
+        Ok(json) => {
+            if let Err(e) = atomic_write(defs::MODULE_STATS_CACHE, json) {
+                log::debug!("Failed to write module stats cache: {}", e);
+            }
+        }
+        Err(e) => log::debug!("Failed to serialize module stats cache: {}", e),
+    }
+}
+
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(hash, |h, b| (h ^ u64::from(*b)).wrapping_mul(FNV_PRIME))
+}
+
+fn hash_metadata(hash: u64, meta: &fs::Metadata) -> u64 {
+    [meta.len() as i64, meta.mtime(), meta.mtime_nsec()]
+        .iter()
+        .fold(hash, |h, v| fnv1a(h, &v.to_le_bytes()))
+}
+
+fn manifest(root: &Path, partitions: &[String]) -> Option<String> {
+    let metadata = fs::symlink_metadata(root).ok()?;
+    let mut hash = hash_metadata(FNV_OFFSET, &metadata);
+    hash = fnv1a(
+        hash,
+        &fs::read(root.join("module.prop")).unwrap_or_default(),
+    );
+
+    for partition in partitions {
+        for entry in WalkDir::new(root.join(partition))
+            .sort_by_file_name()
+            .into_iter()
+            .flatten()
+        {
+            let Ok(meta) = entry.metadata() else {
+                continue;
+            };
+            hash = fnv1a(
+                hash,
+                entry.path().strip_prefix(root).ok()?.as_os_str().as_bytes(),
+            );
+            hash = fnv1a(hash, &[0]);
+            hash = hash_metadata(hash, &meta);
+        }
+    }
+
+    Some(format!("{:016x}", hash))
+}
+
+fn scan_partition(path: &Path) -> PartitionStats {
+    let mut stats = PartitionStats::default();
+
+    for entry in WalkDir::new(path).min_depth(1).into_iter().flatten() {
+        let file_type = entry.file_type();
+        if file_type.is_dir() {
+            stats.dirs += 1;
+        } else if file_type.is_symlink() {
+            stats.symlinks += 1;
+        } else {
+            stats.files += 1;
+            if let Ok(metadata) = entry.metadata() {
+                stats.bytes += metadata.len();
+            }
+        }
+    }
+
+    stats
+}
+
+fn scan_root(root: &Path, partitions: &[String]) -> BTreeMap<String, PartitionStats> {
+    partitions
+        .iter()
+        .filter(|p| root.join(p).is_dir())
+        .map(|p| (p.clone(), scan_partition(&root.join(p))))
+        .collect()
+}
+
+struct Scanned {
+    key: String,
+    entry: CacheEntry,
+    fresh: bool,
+}
+
+fn cached_scan(
+    cache: &StatsCache,
+    key: String,
+    root: &Path,
+    partitions: &[String],
+) -> Option<Scanned> {
+    let manifest = manifest(root, partitions)?;
+
+    if let Some(entry) = cache.get(&key)
+        && entry.manifest == manifest
+    {
+        return Some(Scanned {
+            entry: entry.clone(),
+            key,
+            fresh: false,
+        });
+    }
+
+    Some(Scanned {
+        key,
+        entry: CacheEntry {
+            manifest,
+            partitions: scan_root(root, partitions),
+        },
+        fresh: true,
+    })
+}
+
+pub fn collect(
+    modules: &[Module],
+    storage_root: Option<&Path>,
+    cfg: &config::Config,
+) -> HashMap<String, ModuleStats> {
+    let partitions = partitions::names(&cfg.partitions);
+
+    let cache = load_cache();
+
+    let scanned: Vec<(String, Option<Scanned>, Option<Scanned>)> = modules
+        .par_iter()
+        .map(|m| {
+            let source = cached_scan(
+                &cache,
+                format!("source:{}", m.id),
+                &m.source_path,
+                &partitions,
+            );
+            let storage = storage_root.and_then(|root| {
+                cached_scan(
+                    &cache,
+                    format!("storage:{}", m.id),
+                    &root.join(&m.id),
+                    &partitions,
+                )
+            });
+            (m.id.clone(), source, storage)
+        })
+        .collect();
+
+    let mut new_cache = StatsCache::new();
+    let mut dirty = false;
+    let mut result = HashMap::new();
+
+    for (id, source, storage) in scanned {
+        let mut stats = ModuleStats::default();
+        if let Some(scanned) = source {
+            stats.source = scanned.entry.partitions.clone();
+            dirty |= scanned.fresh;
+            new_cache.insert(scanned.key, scanned.entry);
+        }
+        if let Some(scanned) = storage {
+            stats.storage = scanned.entry.partitions.clone();
+            dirty |= scanned.fresh;
+            new_cache.insert(scanned.key, scanned.entry);
+        }
+        result.insert(id, stats);
+    }
+
+    if dirty || new_cache.len() != cache.len() {
+        save_cache(&new_cache);
+    }
+
+    result
+}
@@ -1,6 +1,11 @@
-use std::path::{Path, PathBuf};
+use std::{
+    os::fd::{AsRawFd, OwnedFd},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, bail};
 
-use anyhow::Result;
+use rustix::mount::{UnmountFlags, unmount};
 
 use crate::{
     conf::config::Config,
@@ -12,13 +17,18 @@ use crate::{
         },
         state,
         storage::{self, StorageHandle},
+        verify,
     },
+    mount::hardening,
+    sys::namespace,
+    utils,
 };
 
 pub struct Init;
 
 pub struct StorageReady {
     pub handle: StorageHandle,
+    pub modules: Vec<inventory::Module>,
 }
 
 pub struct ModulesReady {
@@ -29,18 +39,23 @@ pub struct ModulesReady {
 pub struct Planned {
     pub handle: StorageHandle,
     pub plan: planner::MountPlan,
+    pub isolated: Vec<(String, planner::MountPlan)>,
 }
 
 pub struct Executed {
     pub handle: StorageHandle,
     pub plan: planner::MountPlan,
     pub result: executor::ExecutionResult,
+    pub isolated: Vec<state::IsolatedNamespace>,
+    pub verification: verify::VerifyReport,
+    pub storage_view: Option<OwnedFd>,
 }
 
 pub struct MountController<S> {
     config: Config,
     state: S,
     tempdir: PathBuf,
+    target_ns: Option<PathBuf>,
 }
 
 impl MountController<Init> {
@@ -52,7 +67,18 @@ impl MountController<Init> {
             config,
             state: Init,
             tempdir: tempdir.as_ref().to_path_buf(),
+            target_ns: None,
+        }
+    }
+
+    pub fn select_namespace(mut self, pid: Option<i32>) -> Result<Self> {
+        if let Some(pid) = pid {
+            let ns = namespace::process_path(pid)?;
+            log::info!("Targeting mount namespace of pid {}", pid);
+            self.target_ns = Some(ns);
         }
+
+        Ok(self)
     }
 
     pub fn init_storage(
@@ -60,10 +86,29 @@ impl MountController<Init> {
         mnt_base: &Path,
         img_path: &Path,
     ) -> Result<MountController<StorageReady>> {
+        let modules = inventory::scan(&self.config.moduledir, &self.config)?;
+
+        if self.target_ns.is_some() {
+            let runtime = state::RuntimeState::load()?;
+            let mount_point = if runtime.mount_point.as_os_str().is_empty() {
+                mnt_base
+            } else {
+                runtime.mount_point.as_path()
+            };
+            let handle = storage::attach(mount_point, &runtime.storage_mode)?;
+
+            return Ok(MountController {
+                config: self.config,
+                state: StorageReady { handle, modules },
+                tempdir: self.tempdir,
+                target_ns: self.target_ns,
+            });
+        }
+
         let handle = storage::setup(
             mnt_base,
             img_path,
-            &self.config.moduledir,
+            &modules,
             matches!(
                 self.config.overlay_mode,
                 crate::conf::config::OverlayMode::Ext4
@@ -78,17 +123,35 @@ impl MountController<Init> {
 
         Ok(MountController {
             config: self.config,
-            state: StorageReady { handle },
+            state: StorageReady { handle, modules },
             tempdir: self.tempdir,
+            target_ns: self.target_ns,
         })
     }
 }
 
 impl MountController<StorageReady> {
     pub fn scan_and_sync(mut self) -> Result<MountController<ModulesReady>> {
-        let modules = inventory::scan(&self.config.moduledir, &self.config)?;
+        let modules = std::mem::take(&mut self.state.modules);
+
+        if self.target_ns.is_some() {
+            return Ok(MountController {
+                config: self.config,
+                state: ModulesReady {
+                    handle: self.state.handle,
+                    modules,
+                },
+                tempdir: self.tempdir,
+                target_ns: self.target_ns,
+            });
+        }
 
-        sync::perform_sync(&modules, self.state.handle.mount_point())?;
+        sync::perform_sync(
+            &modules,
+            self.state.handle.mount_point(),
+            &self.config.partitions,
+        )?;
+        self.state.handle.report_usage();
 
         if self.state.handle.mode() == "erofs_staging" {
             let needs_magic = modules.iter().any(|m| {
@@ -116,38 +179,123 @@ impl MountController<StorageReady> {
                 modules,
             },
             tempdir: self.tempdir,
+            target_ns: self.target_ns,
         })
     }
 }
 
 impl MountController<ModulesReady> {
+    pub fn select_module(mut self, module_id: Option<&str>) -> Result<Self> {
+        if let Some(id) = module_id {
+            self.state.modules.retain(|m| m.id == id);
+            if self.state.modules.is_empty() {
+                bail!("Module {} is not installed or not enabled", id);
+            }
+        }
+
+        Ok(self)
+    }
+
     pub fn generate_plan(self) -> Result<MountController<Planned>> {
-        let plan = planner::generate(
-            &self.config,
-            &self.state.modules,
-            self.state.handle.mount_point(),
-        )?;
+        let targeted = self.target_ns.is_some();
+        let (isolated_modules, modules): (Vec<_>, Vec<_>) = self
+            .state
+            .modules
+            .into_iter()
+            .partition(|m| !targeted && self.config.isolated_modules.contains(&m.id));
+
+        let plan = planner::generate(&self.config, &modules, self.state.handle.mount_point())?;
+
+        let mut isolated = Vec::new();
+        for module in isolated_modules {
+            let module_plan = planner::generate(
+                &self.config,
+                std::slice::from_ref(&module),
+                self.state.handle.mount_point(),
+            )?;
+            isolated.push((module.id, module_plan));
+        }
 
         Ok(MountController {
             config: self.config,
             state: Planned {
                 handle: self.state.handle,
                 plan,
+                isolated,
             },
             tempdir: self.tempdir,
+            target_ns: self.target_ns,
         })
     }
 }
 
 impl MountController<Planned> {
+    fn execute_isolated(
+        &self,
+        module_id: &str,
+        plan: &planner::MountPlan,
+    ) -> Result<state::IsolatedNamespace> {
+        let pin = namespace::pin_path(module_id)?;
+        namespace::create(&pin)?;
+
+        let mut config = self.config.clone();
+        config.disable_umount = true;
+
+        let result = namespace::run_in(&pin, || {
+            executor::execute(plan, &config, &self.tempdir, &NativeMount)
+        })??;
+
+        log::info!(
+            "module {} mounted in isolated namespace {}",
+            module_id,
+            pin.display()
+        );
+
+        Ok(state::IsolatedNamespace {
+            module: module_id.to_string(),
+            pin,
+            targets: result.targets,
+        })
+    }
+
     pub fn execute(self) -> Result<MountController<Executed>> {
+        let mut isolated = Vec::new();
+        for (module_id, plan) in &self.state.isolated {
+            match self.execute_isolated(module_id, plan) {
+                Ok(ns) => isolated.push(ns),
+                Err(e) => log::error!("Failed to mount isolated module {}: {:#}", module_id, e),
+            }
+        }
+
+        let storage_view = match namespace::detach_tree(self.state.handle.mount_point()) {
+            Ok(fd) => Some(fd),
+            Err(e) => {
+                log::warn!("Storage will not be available for verification: {:#}", e);
+                None
+            }
+        };
+
         let driver = NativeMount;
-        let result = executor::execute(
-            &self.state.plan,
-            &self.config,
-            self.tempdir.clone(),
-            &driver,
-        )?;
+        let result = match &self.target_ns {
+            Some(ns) => {
+                let storage = namespace::detach_tree(self.state.handle.mount_point())?;
+                namespace::run_in(ns, || -> Result<executor::ExecutionResult> {
+                    namespace::attach_tree(&storage, &self.tempdir)?;
+                    let result =
+                        executor::execute(&self.state.plan, &self.config, &self.tempdir, &driver);
+                    if result.is_err() {
+                        let _ = unmount(&self.tempdir, UnmountFlags::DETACH);
+                    }
+                    result
+                })??
+            }
+            None => executor::execute(
+                &self.state.plan,
+                &self.config,
+                self.tempdir.clone(),
+                &driver,
+            )?,
+        };
 
         Ok(MountController {
             config: self.config,
@@ -155,14 +303,52 @@ impl MountController<Planned> {
                 handle: self.state.handle,
                 plan: self.state.plan,
                 result,
+                isolated,
+                verification: verify::VerifyReport::default(),
+                storage_view,
             },
             tempdir: self.tempdir,
+            target_ns: self.target_ns,
         })
     }
 }
 
 impl MountController<Executed> {
+    pub fn verify(mut self) -> Result<Self> {
+        let Some(view) = &self.state.storage_view else {
+            return Ok(self);
+        };
+        let storage_view = PathBuf::from(format!("/proc/self/fd/{}", view.as_raw_fd()));
+        let run = || {
+            verify::run(
+                &self.config,
+                &self.state.plan,
+                &self.state.result,
+                self.state.handle.mount_point(),
+                &storage_view,
+            )
+        };
+
+        let report = match &self.target_ns {
+            Some(ns) => namespace::run_in(ns, run)?,
+            None => run(),
+        };
+        self.state.verification = report;
+
+        Ok(self)
+    }
+
     pub fn finalize(self) -> Result<()> {
+        if let Some(ns) = &self.target_ns {
+            log::info!(
+                "Mounted {} overlay and {} magic modules into {}; runtime state left untouched",
+                self.state.result.overlay_module_ids.len(),
+                self.state.result.magic_module_ids.len(),
+                ns.display()
+            );
+            return Ok(());
+        }
+
         modules::update_description(
             self.state.handle.mode(),
             self.state.result.overlay_module_ids.len(),
@@ -180,16 +366,140 @@ impl MountController<Executed> {
         active_mounts.sort();
         active_mounts.dedup();
 
-        let state = state::RuntimeState::new(
+        let mut state = state::RuntimeState::new(
             self.state.handle.mode().to_string(),
             self.state.handle.mount_point().to_path_buf(),
             self.state.result.overlay_module_ids,
             self.state.result.magic_module_ids,
             active_mounts,
         );
+        state.writable_mounts = self.state.result.writable_targets;
+        state.targets = self.state.result.targets;
+        state.mount_timing = self.state.result.timing;
+        state.magic_report = self.state.result.magic_report;
+        state.hardened_mounts = hardening::take_records();
+        state.isolated = self.state.isolated;
+        state.verification = self.state.verification;
+        state.tool_invocations = utils::take_tool_invocations();
 
         let _ = state.save();
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        process::{Child, Command},
+        thread,
+        time::Duration,
+    };
+
+    use procfs::process::Process;
+
+    use super::*;
+    use crate::{core::ops::planner::OverlayOperation, sys::mount::mount_tmpfs};
+
+    struct Holder(Child);
+
+    impl Drop for Holder {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    struct Mounted(PathBuf);
+
+    impl Drop for Mounted {
+        fn drop(&mut self) {
+            let _ = unmount(&self.0, UnmountFlags::DETACH);
+        }
+    }
+
+    fn mounts(pid: Option<i32>) -> Vec<(i32, PathBuf)> {
+        let process = match pid {
+            Some(pid) => Process::new(pid).unwrap(),
+            None => Process::myself().unwrap(),
+        };
+        process
+            .mountinfo()
+            .unwrap()
+            .0
+            .into_iter()
+            .map(|m| (m.mnt_id, m.mount_point))
+            .collect()
+    }
+
+    #[test]
+    fn target_namespace_leaves_global_mounts_untouched() {
+        if !rustix::process::geteuid().is_root() {
+            return;
+        }
+        let Ok(child) = Command::new("unshare")
+            .args(["-m", "--propagation", "private", "sleep", "30"])
+            .spawn()
+        else {
+            return;
+        };
+        let holder = Holder(child);
+        let pid = holder.0.id() as i32;
+        let ns = namespace::process_path(pid).unwrap();
+        let own_ns = fs::read_link("/proc/self/ns/mnt").unwrap();
+        for _ in 0..100 {
+            if fs::read_link(&ns).is_ok_and(|link| link != own_ns) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let storage_dir = dir.path().join("storage");
+        let target = dir.path().join("target");
+        fs::create_dir_all(&target).unwrap();
+        mount_tmpfs(&storage_dir, "hybrid_test").unwrap();
+        let _storage = Mounted(storage_dir.clone());
+        let layer = storage_dir.join("module/target");
+        fs::create_dir_all(&layer).unwrap();
+        fs::write(layer.join("file"), "module").unwrap();
+
+        let before = mounts(None);
+
+        let config = Config {
+            disable_umount: true,
+            ..Default::default()
+        };
+        let plan = planner::MountPlan {
+            overlay_ops: vec![OverlayOperation {
+                partition_name: "system".to_string(),
+                target: target.display().to_string(),
+                lowerdirs: vec![layer],
+                magic_fallback: false,
+            }],
+            ..Default::default()
+        };
+        let controller = MountController {
+            config,
+            state: Planned {
+                handle: storage::attach(&storage_dir, "tmpfs").unwrap(),
+                plan,
+                isolated: Vec::new(),
+            },
+            tempdir: storage_dir.clone(),
+            target_ns: Some(ns),
+        };
+        controller
+            .execute()
+            .unwrap()
+            .verify()
+            .unwrap()
+            .finalize()
+            .unwrap();
+
+        assert_eq!(mounts(None), before);
+        assert!(mounts(Some(pid)).iter().any(|(_, m)| *m == target));
+        assert!(!target.join("file").exists());
+    }
+}
@@ -1,8 +1,12 @@
 pub mod backend;
+pub mod capacity;
 pub mod inventory;
 pub mod manager;
 pub mod ops;
+pub mod rw;
 pub mod state;
+pub mod status;
 pub mod storage;
+pub mod verify;
 
 pub use manager::MountController;
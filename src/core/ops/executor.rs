@@ -1,16 +1,27 @@
-use std::{collections::HashSet, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::Instant,
+};
 
 use anyhow::Result;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     conf::config,
     core::{
-        backend::MountDriver,
-        ops::planner::{MountPlan, OverlayOperation},
+        backend::{MagicMountResult, MountDriver, OverlayMountResult},
+        ops::{
+            planner::{MountPlan, OverlayOperation},
+            schedule,
+        },
+        rw,
     },
     defs,
     mount::{
-        magic_mount,
+        hardening,
+        magic_mount::{self, MagicMountReport},
         overlayfs::{self, utils::umount_dir},
         umount_mgr,
     },
@@ -20,6 +31,92 @@ use crate::{
 pub struct ExecutionResult {
     pub overlay_module_ids: Vec<String>,
     pub magic_module_ids: Vec<String>,
+    pub writable_targets: Vec<String>,
+    pub targets: Vec<TargetRecord>,
+    pub timing: MountTiming,
+    pub magic_report: MagicMountReport,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mechanism {
+    Overlay,
+    Magic,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TargetRecord {
+    pub target: String,
+    pub mechanism: Mechanism,
+    pub modules: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MountTiming {
+    pub serial: bool,
+    pub ops: usize,
+    pub waves: usize,
+    pub wall_ms: u64,
+    pub serial_estimate_ms: u64,
+    pub saved_ms: u64,
+}
+
+fn mount_overlays<D>(
+    plan: &MountPlan,
+    config: &config::Config,
+    driver: &D,
+    timing: &mut MountTiming,
+) -> Vec<Result<OverlayMountResult>>
+where
+    D: MountDriver + ?Sized,
+{
+    let ops = &plan.overlay_ops;
+    let waves: Vec<Vec<usize>> = if config.force_serial_mount {
+        (0..ops.len()).map(|idx| vec![idx]).collect()
+    } else {
+        schedule::waves(ops)
+    };
+
+    let partitions: HashSet<&str> = ops.iter().map(|op| op.partition_name.as_str()).collect();
+    for partition in partitions {
+        overlayfs::options::resolve(&config.overlay_options_for(partition));
+    }
+
+    let started = Instant::now();
+    let mut outcomes: Vec<Option<Result<OverlayMountResult>>> = ops.iter().map(|_| None).collect();
+
+    for wave in &waves {
+        let results: Vec<(usize, Result<OverlayMountResult>, u64)> = wave
+            .par_iter()
+            .map(|&idx| {
+                let op_started = Instant::now();
+                let result = driver.mount_overlay(&ops[idx], config);
+                (idx, result, op_started.elapsed().as_millis() as u64)
+            })
+            .collect();
+
+        for (idx, result, elapsed_ms) in results {
+            timing.serial_estimate_ms += elapsed_ms;
+            outcomes[idx] = Some(result);
+        }
+    }
+
+    timing.serial = config.force_serial_mount;
+    timing.ops = ops.len();
+    timing.waves = waves.len();
+    timing.wall_ms = started.elapsed().as_millis() as u64;
+    timing.saved_ms = timing.serial_estimate_ms.saturating_sub(timing.wall_ms);
+
+    log::info!(
+        "mounted {} overlay targets in {} waves: {}ms wall, {}ms serial estimate",
+        timing.ops,
+        timing.waves,
+        timing.wall_ms,
+        timing.serial_estimate_ms
+    );
+
+    outcomes.into_iter().flatten().collect()
 }
 
 pub fn execute<P, D>(
@@ -34,37 +131,122 @@ where
 {
     let mut final_magic_ids: HashSet<String> = plan.magic_module_ids.iter().cloned().collect();
     let mut final_overlay_ids: HashSet<String> = HashSet::new();
+    let mut magic_scopes: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    let mut writable_targets = Vec::new();
+    let mut targets = Vec::new();
+    let mut timing = MountTiming::default();
+    let mut magic_report = MagicMountReport::default();
+
+    hardening::configure(&config.hardening);
 
     if driver.is_supported()? {
-        for op in &plan.overlay_ops {
-            match driver.mount_overlay(op, config) {
-                Ok(ids) => {
-                    final_overlay_ids.extend(ids);
+        let outcomes = mount_overlays(plan, config, driver, &mut timing);
+        for (op, outcome) in plan.overlay_ops.iter().zip(outcomes) {
+            let involved_modules: Vec<String> = op
+                .lowerdirs
+                .iter()
+                .filter_map(|p| utils::extract_module_id(p))
+                .collect();
+
+            match outcome {
+                Ok(result) => {
+                    final_overlay_ids.extend(result.module_ids);
+                    if result.writable {
+                        writable_targets.push(op.target.clone());
+                    }
+                    targets.push(TargetRecord {
+                        target: op.target.clone(),
+                        mechanism: Mechanism::Overlay,
+                        modules: involved_modules,
+                    });
                 }
-                Err(_) => {
-                    let involved_modules: Vec<String> = op
-                        .lowerdirs
-                        .iter()
-                        .filter_map(|p| utils::extract_module_id(p))
-                        .collect();
-                    final_magic_ids.extend(involved_modules);
+                Err(e) if !op.magic_fallback => {
+                    log::error!(
+                        "overlay on {} failed and magic mount cannot take over: {:#}",
+                        op.target,
+                        e
+                    );
+                    targets.push(TargetRecord {
+                        target: op.target.clone(),
+                        mechanism: Mechanism::Failed,
+                        modules: involved_modules,
+                    });
+                }
+                Err(e) => {
+                    log::warn!(
+                        "overlay on {} failed, falling back to magic mount: {:#}",
+                        op.target,
+                        e
+                    );
+                    for lowerdir in &op.lowerdirs {
+                        match utils::split_module_path(lowerdir) {
+                            Some((id, relative)) => {
+                                magic_scopes.entry(id).or_default().push(relative)
+                            }
+                            None => final_magic_ids.extend(utils::extract_module_id(lowerdir)),
+                        }
+                    }
+                    targets.push(TargetRecord {
+                        target: op.target.clone(),
+                        mechanism: Mechanism::Magic,
+                        modules: involved_modules,
+                    });
                 }
             }
         }
         final_overlay_ids.retain(|id| !final_magic_ids.contains(id));
+        magic_scopes.retain(|id, _| !final_magic_ids.contains(id));
+        final_magic_ids.extend(magic_scopes.keys().cloned());
     } else {
-        final_magic_ids.extend(plan.overlay_module_ids.clone());
+        for op in &plan.overlay_ops {
+            let modules: Vec<String> = op
+                .lowerdirs
+                .iter()
+                .filter_map(|p| utils::extract_module_id(p))
+                .collect();
+            let mechanism = if op.magic_fallback {
+                final_magic_ids.extend(modules.iter().cloned());
+                Mechanism::Magic
+            } else {
+                log::error!("overlay unsupported, {} cannot be mounted", op.target);
+                Mechanism::Failed
+            };
+            targets.push(TargetRecord {
+                target: op.target.clone(),
+                mechanism,
+                modules,
+            });
+        }
     }
 
-    let mut magic_queue: Vec<String> = final_magic_ids.iter().cloned().collect();
-    magic_queue.sort();
-
-    if !magic_queue.is_empty() {
-        let magic_need_ids: HashSet<String> = magic_queue.into_iter().collect();
-        if let Ok(mounted_ids) = driver.mount_magic(&magic_need_ids, config, tempdir.as_ref()) {
-            final_magic_ids.retain(|id| mounted_ids.contains(id));
-        } else {
-            final_magic_ids.clear();
+    if !final_magic_ids.is_empty() {
+        match driver.mount_magic(&final_magic_ids, &magic_scopes, config, tempdir.as_ref()) {
+            Ok(result) => {
+                final_magic_ids.retain(|id| result.module_ids.contains(id));
+                for point in &result.report.mount_points {
+                    if targets.iter().any(|t| {
+                        t.mechanism == Mechanism::Magic && point.path.starts_with(&t.target)
+                    }) {
+                        continue;
+                    }
+                    targets.push(TargetRecord {
+                        target: point.path.display().to_string(),
+                        mechanism: Mechanism::Magic,
+                        modules: point.modules.clone(),
+                    });
+                }
+                magic_report = result.report;
+            }
+            Err(e) => {
+                log::error!("magic mount failed: {:#}", e);
+                final_magic_ids.clear();
+                for record in targets
+                    .iter_mut()
+                    .filter(|r| r.mechanism == Mechanism::Magic)
+                {
+                    record.mechanism = Mechanism::Failed;
+                }
+            }
         }
     }
 
@@ -83,10 +265,15 @@ where
 
     result_overlay.sort();
     result_magic.sort();
+    writable_targets.sort();
 
     Ok(ExecutionResult {
         overlay_module_ids: result_overlay,
         magic_module_ids: result_magic,
+        writable_targets,
+        targets,
+        timing,
+        magic_report,
     })
 }
 
@@ -97,7 +284,11 @@ impl MountDriver for NativeMount {
         overlayfs::utils::is_overlay_supported()
     }
 
-    fn mount_overlay(&self, op: &OverlayOperation, config: &config::Config) -> Result<Vec<String>> {
+    fn mount_overlay(
+        &self,
+        op: &OverlayOperation,
+        config: &config::Config,
+    ) -> Result<OverlayMountResult> {
         let involved_modules: Vec<String> = op
             .lowerdirs
             .iter()
@@ -110,16 +301,15 @@ impl MountDriver for NativeMount {
             .map(|p| p.display().to_string())
             .collect();
 
-        let rw_root = Path::new(defs::SYSTEM_RW_DIR);
-        let part_rw = rw_root.join(&op.partition_name);
-        let upper = part_rw.join("upperdir");
-        let work = part_rw.join("workdir");
-
-        let (upper_opt, work_opt) = if upper.exists() && work.exists() {
-            (Some(upper), Some(work))
-        } else {
-            (None, None)
+        let (upper_opt, work_opt) = match rw::layer_dirs(&op.partition_name, &op.target) {
+            Ok(Some((upper, work))) => (Some(upper), Some(work)),
+            Ok(None) => (None, None),
+            Err(e) => {
+                log::warn!("Mounting {} read-only: {:#}", op.target, e);
+                (None, None)
+            }
         };
+        let writable = upper_opt.is_some();
 
         let mut mount_source = config.mountsource.clone();
 
@@ -130,28 +320,39 @@ impl MountDriver for NativeMount {
             mount_source = "overlay".to_string();
         }
 
-        overlayfs::overlayfs::mount_overlay(
+        let params = overlayfs::options::resolve(&config.overlay_options_for(&op.partition_name));
+
+        let mounted = overlayfs::overlayfs::mount_overlay(
             &op.target,
             &lowerdir_strings,
             work_opt,
             upper_opt,
             &mount_source,
+            &params,
         )?;
 
+        for mount_point in &mounted {
+            hardening::apply(mount_point, writable && *mount_point == op.target);
+        }
+
         #[cfg(any(target_os = "linux", target_os = "android"))]
         if !config.disable_umount {
             let _ = umount_mgr::send_umountable(&op.target);
         }
 
-        Ok(involved_modules)
+        Ok(OverlayMountResult {
+            module_ids: involved_modules,
+            writable,
+        })
     }
 
     fn mount_magic(
         &self,
         ids: &HashSet<String>,
+        scopes: &HashMap<String, Vec<PathBuf>>,
         config: &config::Config,
         tempdir: &Path,
-    ) -> Result<Vec<String>> {
+    ) -> Result<MagicMountResult> {
         let magic_ws_path = tempdir.join("magic_workspace");
 
         if matches!(config.overlay_mode, config::OverlayMode::Erofs) {
@@ -162,15 +363,29 @@ impl MountDriver for NativeMount {
             std::fs::create_dir_all(&magic_ws_path)?;
         }
 
-        magic_mount::magic_mount(
+        let report = magic_mount::magic_mount(
             &magic_ws_path,
             tempdir,
             &config.mountsource,
             &config.partitions,
             ids.clone(),
+            scopes,
             !config.disable_umount,
         )?;
 
-        Ok(ids.iter().cloned().collect())
+        let failed: HashSet<&String> = report
+            .failures
+            .iter()
+            .filter_map(|f| f.module.as_ref())
+            .collect();
+
+        Ok(MagicMountResult {
+            module_ids: ids
+                .iter()
+                .filter(|id| !failed.contains(id))
+                .cloned()
+                .collect(),
+            report,
+        })
     }
 }
@@ -1,3 +1,4 @@
 pub mod executor;
 pub mod planner;
+pub mod schedule;
 pub mod sync;
@@ -1,7 +1,7 @@
 use std::{
     collections::{HashMap, HashSet, VecDeque},
     fs,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
 };
 
 use anyhow::Result;
@@ -12,7 +12,12 @@ use walkdir::WalkDir;
 use crate::{
     conf::config,
     core::inventory::{Module, MountMode},
-    defs, utils,
+    defs,
+    sys::{
+        apex,
+        partitions::{self, Partition},
+    },
+    utils,
 };
 
 #[derive(Debug, Clone)]
@@ -20,6 +25,7 @@ pub struct OverlayOperation {
     pub partition_name: String,
     pub target: String,
     pub lowerdirs: Vec<PathBuf>,
+    pub magic_fallback: bool,
 }
 
 #[derive(Debug, Default)]
@@ -27,6 +33,7 @@ pub struct MountPlan {
     pub overlay_ops: Vec<OverlayOperation>,
     pub overlay_module_ids: Vec<String>,
     pub magic_module_ids: Vec<String>,
+    pub diagnostics: Vec<DiagnosticIssue>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -123,7 +130,10 @@ impl MountPlan {
             })
             .collect();
 
-        let mut report = AnalysisReport::default();
+        let mut report = AnalysisReport {
+            diagnostics: self.diagnostics.clone(),
+            ..Default::default()
+        };
         for (c, d) in results {
             report.conflicts.extend(c);
             report.diagnostics.extend(d);
@@ -145,19 +155,194 @@ struct ProcessingItem {
     partition_label: String,
 }
 
+fn is_plain_relative(path: &Path) -> bool {
+    path.components().count() > 0 && path.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+fn check_custom_target(config: &config::Config, target: &str) -> Result<PathBuf, String> {
+    let path = Path::new(target);
+    let relative = path
+        .strip_prefix("/")
+        .map_err(|_| "target is not an absolute path".to_string())?;
+    if !is_plain_relative(relative) {
+        return Err("target must be a plain path below /".to_string());
+    }
+
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    for candidate in [path, canonical.as_path()] {
+        if let Some(prefix) = defs::FORBIDDEN_TARGET_PREFIXES
+            .iter()
+            .find(|p| candidate.starts_with(p))
+        {
+            return Err(format!("{} is never a permitted target", prefix));
+        }
+        if !config
+            .allowed_target_prefixes
+            .iter()
+            .any(|p| candidate.starts_with(p))
+        {
+            return Err(format!(
+                "{} is outside allowed_target_prefixes",
+                candidate.display()
+            ));
+        }
+    }
+
+    if !canonical.is_dir() {
+        return Err("target directory does not exist".to_string());
+    }
+    Ok(canonical)
+}
+
+fn plan_custom_targets(
+    config: &config::Config,
+    module: &Module,
+    content_path: &Path,
+    overlay_groups: &mut HashMap<PathBuf, Vec<PathBuf>>,
+    custom_targets: &mut HashSet<PathBuf>,
+    diagnostics: &mut Vec<DiagnosticIssue>,
+) -> bool {
+    let mut planned = false;
+
+    for (subdir, target) in &module.rules.targets {
+        if matches!(module.rules.get_mode(subdir), MountMode::Ignore) {
+            continue;
+        }
+
+        let source = content_path.join(subdir);
+        let checked = if !is_plain_relative(Path::new(subdir)) {
+            Err(format!("invalid module subdirectory '{}'", subdir))
+        } else if !source.is_dir() {
+            Err(format!("{} is not a directory", source.display()))
+        } else {
+            check_custom_target(config, target)
+        };
+
+        match checked {
+            Ok(target_path) => {
+                log::debug!(
+                    "{}: custom target {} -> {}",
+                    module.id,
+                    subdir,
+                    target_path.display()
+                );
+                overlay_groups
+                    .entry(target_path.clone())
+                    .or_default()
+                    .push(source);
+                custom_targets.insert(target_path);
+                planned = true;
+            }
+            Err(reason) => {
+                log::warn!(
+                    "{}: refusing custom target {} -> {}: {}",
+                    module.id,
+                    subdir,
+                    target,
+                    reason
+                );
+                diagnostics.push(DiagnosticIssue {
+                    level: DiagnosticLevel::Warning,
+                    context: module.id.clone(),
+                    message: format!("Custom target {} -> {} refused: {}", subdir, target, reason),
+                });
+            }
+        }
+    }
+
+    planned
+}
+
+fn resolve_apex(
+    module_id: &str,
+    module_source: &Path,
+    system_target: &Path,
+    active: &[apex::ApexInfo],
+    overlay_groups: &mut HashMap<PathBuf, Vec<PathBuf>>,
+    diagnostics: &mut Vec<DiagnosticIssue>,
+) {
+    let requested = system_target
+        .file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let (name, version) = apex::split_versioned(&requested);
+
+    let mut report = |message: String| {
+        log::warn!("{}: {}", module_id, message);
+        diagnostics.push(DiagnosticIssue {
+            level: DiagnosticLevel::Warning,
+            context: module_id.to_string(),
+            message,
+        });
+    };
+
+    let Some(info) = active.iter().find(|a| a.name == name) else {
+        report(format!(
+            "APEX {} is not active, skipping {}",
+            name,
+            module_source.display()
+        ));
+        return;
+    };
+
+    if let Some(version) = version
+        && version != info.version
+    {
+        report(format!(
+            "{} targets {}@{} but version {} is active, using the active one",
+            module_source.display(),
+            name,
+            version,
+            info.version
+        ));
+    }
+
+    let mountpoints = info.mountpoints();
+    if mountpoints.is_empty() {
+        report(format!(
+            "APEX {} is listed as active but not mounted, skipping {}",
+            name,
+            module_source.display()
+        ));
+    }
+    for mountpoint in mountpoints {
+        overlay_groups
+            .entry(mountpoint)
+            .or_default()
+            .push(module_source.to_path_buf());
+    }
+}
+
 pub fn generate(
     config: &config::Config,
     modules: &[Module],
     storage_root: &Path,
+) -> Result<MountPlan> {
+    let registry = partitions::resolve(&config.partitions);
+    generate_with(&registry, Path::new("/"), config, modules, storage_root)
+}
+
+pub fn generate_with(
+    registry: &[Partition],
+    sysroot: &Path,
+    config: &config::Config,
+    modules: &[Module],
+    storage_root: &Path,
 ) -> Result<MountPlan> {
     let mut plan = MountPlan::default();
 
     let mut overlay_groups: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    let mut custom_targets: HashSet<PathBuf> = HashSet::new();
 
     let mut overlay_ids = HashSet::new();
     let mut magic_ids = HashSet::new();
 
-    let sensitive_partitions: HashSet<&str> = defs::SENSITIVE_PARTITIONS.iter().cloned().collect();
+    let partitions: HashSet<&str> = registry.iter().map(|p| p.name.as_str()).collect();
+    let sensitive_partitions: HashSet<&str> = registry
+        .iter()
+        .filter(|p| p.sensitive)
+        .map(|p| p.name.as_str())
+        .collect();
 
     for module in modules {
         let mut content_path = storage_root.join(&module.id);
@@ -168,6 +353,17 @@ pub fn generate(
             continue;
         }
 
+        if plan_custom_targets(
+            config,
+            module,
+            &content_path,
+            &mut overlay_groups,
+            &mut custom_targets,
+            &mut plan.diagnostics,
+        ) {
+            overlay_ids.insert(module.id.clone());
+        }
+
         if let Ok(entries) = fs::read_dir(&content_path) {
             for entry in entries.flatten() {
                 let path = entry.path();
@@ -186,16 +382,24 @@ pub fn generate(
                     continue;
                 };
 
-                let mut partitions = HashSet::new();
-                partitions.insert("system".to_string());
-                partitions.extend(config.partitions.clone());
-                partitions.extend(defs::BUILTIN_PARTITIONS.iter().map(|s| s.to_string()));
-
                 if !partitions.contains(dir_name) {
                     continue;
                 }
 
                 let mode = module.rules.get_mode(dir_name);
+                if matches!(mode, MountMode::Magic) && dir_name == "apex" {
+                    log::warn!(
+                        "{}: APEX content cannot be magic mounted, skipping",
+                        module.id
+                    );
+                    plan.diagnostics.push(DiagnosticIssue {
+                        level: DiagnosticLevel::Warning,
+                        context: module.id.clone(),
+                        message: "APEX content cannot be magic mounted, use overlay mode for apex/"
+                            .to_string(),
+                    });
+                    continue;
+                }
                 if matches!(mode, MountMode::Magic) {
                     magic_ids.insert(module.id.clone());
                     continue;
@@ -209,7 +413,7 @@ pub fn generate(
                 let mut queue = VecDeque::new();
                 queue.push_back(ProcessingItem {
                     module_source: path.clone(),
-                    system_target: Path::new("/").join(dir_name),
+                    system_target: sysroot.join(dir_name),
                     partition_label: dir_name.to_string(),
                 });
 
@@ -220,6 +424,21 @@ pub fn generate(
                         partition_label,
                     } = item;
 
+                    if partition_label == "apex"
+                        && system_target.parent() == Some(sysroot.join("apex").as_path())
+                        && let Some(active) = apex::active()
+                    {
+                        resolve_apex(
+                            &module.id,
+                            &module_source,
+                            &system_target,
+                            active,
+                            &mut overlay_groups,
+                            &mut plan.diagnostics,
+                        );
+                        continue;
+                    }
+
                     if !system_target.exists() {
                         continue;
                     }
@@ -297,13 +516,17 @@ pub fn generate(
         }
 
         let partition_name = target_path
+            .strip_prefix(sysroot)
+            .unwrap_or(&target_path)
             .iter()
-            .nth(1)
+            .next()
             .map(|s| s.to_string_lossy().to_string())
             .unwrap_or_else(|| "unknown".to_string());
 
         plan.overlay_ops.push(OverlayOperation {
             partition_name,
+            magic_fallback: !target_path.starts_with(sysroot.join("apex"))
+                && !custom_targets.contains(&target_path),
             target: target_str,
             lowerdirs: layers,
         });
@@ -311,8 +534,68 @@ pub fn generate(
 
     plan.overlay_module_ids = overlay_ids.into_iter().collect();
     plan.magic_module_ids = magic_ids.into_iter().collect();
+    plan.overlay_ops.sort_by(|a, b| a.target.cmp(&b.target));
     plan.overlay_module_ids.sort();
     plan.magic_module_ids.sort();
 
     Ok(plan)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowing(prefix: &Path) -> config::Config {
+        config::Config {
+            allowed_target_prefixes: vec![prefix.to_string_lossy().into_owned()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn check_custom_target_accepts_existing_dirs_below_allowed_prefix() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let target = root.join("odm_dlkm/etc");
+        fs::create_dir_all(&target).unwrap();
+
+        let config = allowing(&root);
+        assert_eq!(
+            check_custom_target(&config, target.to_str().unwrap()),
+            Ok(target.clone())
+        );
+        assert!(check_custom_target(&config, root.join("missing").to_str().unwrap()).is_err());
+        assert!(
+            check_custom_target(&allowing(Path::new("/mnt")), target.to_str().unwrap()).is_err()
+        );
+    }
+
+    #[test]
+    fn check_custom_target_rejects_non_plain_paths() {
+        let config = allowing(Path::new("/"));
+        for target in ["mnt/vendor", "/mnt/../data", "/", ""] {
+            assert!(check_custom_target(&config, target).is_err(), "{target}");
+        }
+    }
+
+    #[test]
+    fn check_custom_target_never_allows_forbidden_prefixes() {
+        let config = allowing(Path::new("/"));
+        assert!(check_custom_target(&config, "/dev").is_err());
+        assert!(check_custom_target(&config, "/proc/self").is_err());
+    }
+
+    #[test]
+    fn check_custom_target_follows_symlinks_out_of_allowed_prefix() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let allowed = root.join("allowed");
+        let outside = root.join("outside");
+        fs::create_dir_all(&allowed).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, allowed.join("link")).unwrap();
+
+        let config = allowing(&allowed);
+        assert!(check_custom_target(&config, allowed.join("link").to_str().unwrap()).is_err());
+    }
+}
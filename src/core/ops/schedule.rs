@@ -0,0 +1,121 @@
+use std::{collections::HashSet, path::Path};
+
+use procfs::process::{MountOptFields, Process};
+
+use crate::core::ops::planner::OverlayOperation;
+
+fn shared_groups(targets: &[&Path]) -> Vec<HashSet<u32>> {
+    let mounts = match Process::myself().and_then(|p| p.mountinfo()) {
+        Ok(mounts) => mounts,
+        Err(e) => {
+            log::warn!("Failed to read mountinfo for scheduling: {}", e);
+            return vec![HashSet::new(); targets.len()];
+        }
+    };
+
+    targets
+        .iter()
+        .map(|target| {
+            mounts
+                .0
+                .iter()
+                .filter(|m| m.mount_point.starts_with(target))
+                .flat_map(|m| m.opt_fields.iter())
+                .filter_map(|field| match field {
+                    MountOptFields::Shared(group) => Some(*group),
+                    _ => None,
+                })
+                .collect()
+        })
+        .collect()
+}
+
+pub fn waves(ops: &[OverlayOperation]) -> Vec<Vec<usize>> {
+    let targets: Vec<&Path> = ops.iter().map(|op| Path::new(&op.target)).collect();
+    let groups = shared_groups(&targets);
+    let mut levels = vec![0usize; ops.len()];
+
+    for j in 0..ops.len() {
+        for i in 0..j {
+            let related = targets[j].starts_with(targets[i])
+                || targets[i].starts_with(targets[j])
+                || !groups[i].is_disjoint(&groups[j]);
+            if related {
+                levels[j] = levels[j].max(levels[i] + 1);
+            }
+        }
+    }
+
+    let depth = levels.iter().max().map(|l| l + 1).unwrap_or(0);
+    let mut waves = vec![Vec::new(); depth];
+    for (idx, level) in levels.into_iter().enumerate() {
+        waves[level].push(idx);
+    }
+
+    waves
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use rustix::mount::{
+        MountFlags, MountPropagationFlags, UnmountFlags, mount, mount_bind, mount_change, unmount,
+    };
+
+    use super::*;
+
+    struct Mounted(PathBuf);
+
+    impl Drop for Mounted {
+        fn drop(&mut self) {
+            let _ = unmount(&self.0, UnmountFlags::DETACH);
+        }
+    }
+
+    fn op(target: &Path) -> OverlayOperation {
+        OverlayOperation {
+            partition_name: "system".to_string(),
+            target: target.to_string_lossy().into_owned(),
+            lowerdirs: Vec::new(),
+            magic_fallback: false,
+        }
+    }
+
+    #[test]
+    fn waves_order_nested_targets_and_batch_unrelated_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let ops = [
+            op(&root.join("system")),
+            op(&root.join("vendor")),
+            op(&root.join("system/etc")),
+            op(&root.join("system/etc/init")),
+            op(&root.join("product")),
+        ];
+
+        assert_eq!(waves(&ops), [vec![0, 1, 4], vec![2], vec![3]]);
+        assert!(waves(&[]).is_empty());
+    }
+
+    #[test]
+    fn waves_serialize_targets_in_one_peer_group() {
+        if !rustix::process::geteuid().is_root() {
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+        fs::create_dir_all(&a).unwrap();
+        fs::create_dir_all(&b).unwrap();
+
+        mount("tmpfs", &a, c"tmpfs", MountFlags::empty(), None).unwrap();
+        let _a = Mounted(a.clone());
+        mount_change(&a, MountPropagationFlags::SHARED).unwrap();
+        mount_bind(&a, &b).unwrap();
+        let _b = Mounted(b.clone());
+
+        let c = dir.path().join("c");
+        assert_eq!(waves(&[op(&a), op(&b), op(&c)]), [vec![0, 2], vec![1]]);
+    }
+}
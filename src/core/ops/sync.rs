@@ -1,29 +1,45 @@
-use std::{collections::HashSet, fs, path::Path};
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
 
 use anyhow::Result;
 use rayon::prelude::*;
 use walkdir::WalkDir;
 
 use crate::{
-    core::inventory::Module,
+    core::inventory::{Module, attrs::AttrManifest, removals},
     defs,
-    sys::fs::{prune_empty_dirs, set_overlay_opaque, sync_dir},
+    sys::{
+        fs::{make_whiteout, prune_empty_dirs, set_overlay_opaque, sync_dir},
+        partitions,
+    },
 };
 
-pub fn perform_sync(modules: &[Module], target_base: &Path) -> Result<()> {
+pub fn perform_sync(
+    modules: &[Module],
+    target_base: &Path,
+    extra_partitions: &[String],
+) -> Result<()> {
     log::info!("Starting smart module sync to {}", target_base.display());
 
     prune_orphaned_modules(modules, target_base)?;
 
+    let partitions = partitions::names(extra_partitions);
+
     modules.par_iter().for_each(|module| {
         let dst = target_base.join(&module.id);
         let dst_backup = target_base.join(format!(".backup_{}", module.id));
 
-        let has_content = defs::BUILTIN_PARTITIONS.iter().any(|p| {
-            let part_path = module.source_path.join(p);
+        let removed = removals::collect(&module.source_path, extra_partitions);
+        let has_content = !removed.is_empty()
+            || !module.rules.targets.is_empty()
+            || partitions.iter().any(|p| {
+                let part_path = module.source_path.join(p);
 
-            part_path.exists() && has_files_recursive(&part_path)
-        });
+                part_path.exists() && has_files_recursive(&part_path)
+            });
 
         if has_content && should_sync(&module.source_path, &dst) {
             log::info!("Syncing module: {} (Updated/New)", module.id);
@@ -40,6 +56,21 @@ pub fn perform_sync(modules: &[Module], target_base: &Path) -> Result<()> {
                 return;
             }
 
+            apply_removals(&module.id, &tmp_dst, &removed);
+
+            match AttrManifest::load(&module.source_path) {
+                Ok(Some(manifest)) => {
+                    let applied = manifest.apply_tree(&tmp_dst);
+                    log::debug!(
+                        "Applied attribute overrides to {} paths of {}",
+                        applied,
+                        module.id
+                    );
+                }
+                Ok(None) => {}
+                Err(e) => log::warn!("Skipping attribute overrides for {}: {:#}", module.id, e),
+            }
+
             if let Err(e) = prune_empty_dirs(&tmp_dst) {
                 log::warn!("Failed to prune empty dirs for {}: {}", module.id, e);
             }
@@ -82,6 +113,39 @@ pub fn perform_sync(modules: &[Module], target_base: &Path) -> Result<()> {
     Ok(())
 }
 
+fn apply_removals(module_id: &str, root: &Path, removed: &[PathBuf]) {
+    for rel in removed {
+        let target = root.join(rel);
+        let mut marker = target.clone().into_os_string();
+        marker.push(defs::REMOVE_MARKER_SUFFIX);
+        let _ = fs::remove_file(&marker);
+
+        if target.symlink_metadata().is_ok() {
+            log::warn!(
+                "Module {} both ships and removes {}, keeping shipped content",
+                module_id,
+                rel.display()
+            );
+            continue;
+        }
+
+        let result = target
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| make_whiteout(&target));
+        match result {
+            Ok(()) => log::debug!("Created whiteout for {}", target.display()),
+            Err(e) => log::warn!(
+                "Failed to create whiteout for {} in {}: {}",
+                rel.display(),
+                module_id,
+                e
+            ),
+        }
+    }
+}
+
 fn apply_overlay_opaque_flags(root: &Path) -> Result<()> {
     for entry in WalkDir::new(root).min_depth(1).into_iter().flatten() {
         if entry.file_type().is_file()
@@ -0,0 +1,461 @@
+use std::{
+    fs,
+    os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use extattr::lgetxattr;
+use procfs::process::Process;
+use rustix::mount::{UnmountFlags, unmount};
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::{
+    conf::config::Config,
+    defs,
+    sys::{
+        fs::{OVERLAY_OPAQUE_XATTR, lgetfilecon, lsetfilecon, sync_dir},
+        partitions,
+    },
+};
+
+const UPPER_DIR: &str = "upperdir";
+const WORK_DIR: &str = "workdir";
+const DEFAULT_SELINUX_CONTEXT: &str = "u:object_r:system_file:s0";
+const LAYER_PREFIX: &str = "%2F";
+
+#[derive(Debug, Serialize)]
+pub struct RwStatus {
+    pub partition: String,
+    pub enabled: bool,
+    pub mounted_targets: Vec<String>,
+    pub entries: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Written,
+    Deleted,
+    Opaque,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RwChange {
+    pub path: String,
+    pub kind: ChangeKind,
+}
+
+fn partition_dir(partition: &str) -> PathBuf {
+    Path::new(defs::SYSTEM_RW_DIR).join(partition)
+}
+
+fn stock_root(partition: &str) -> PathBuf {
+    Path::new("/").join(partition)
+}
+
+pub fn is_enabled(partition: &str) -> bool {
+    let dir = partition_dir(partition);
+    dir.join(UPPER_DIR).is_dir() && dir.join(WORK_DIR).is_dir()
+}
+
+fn mirror_attrs(stock: &Path, dst: &Path) -> Result<()> {
+    let (mode, uid, gid) = match fs::metadata(stock) {
+        Ok(meta) => (meta.mode() & 0o7777, meta.uid(), meta.gid()),
+        Err(_) => (0o755, 0, 0),
+    };
+    let context = lgetfilecon(stock).unwrap_or_else(|_| DEFAULT_SELINUX_CONTEXT.to_string());
+
+    fs::set_permissions(dst, fs::Permissions::from_mode(mode))?;
+    std::os::unix::fs::chown(dst, Some(uid), Some(gid))?;
+    lsetfilecon(dst, &context)?;
+
+    Ok(())
+}
+
+fn create_mirrored(root: &Path, stock: &Path, rel: &Path) -> Result<PathBuf> {
+    let mut current = root.to_path_buf();
+    let mut current_stock = stock.to_path_buf();
+
+    if !current.exists() {
+        fs::create_dir_all(&current)?;
+        mirror_attrs(&current_stock, &current)?;
+    }
+
+    for component in rel.components() {
+        current.push(component);
+        current_stock.push(component);
+        match fs::create_dir(&current) {
+            Ok(()) => mirror_attrs(&current_stock, &current)?,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(current)
+}
+
+fn layer_name(rel: &Path) -> String {
+    Path::new("/")
+        .join(rel)
+        .to_string_lossy()
+        .replace('%', "%25")
+        .replace('/', "%2F")
+}
+
+fn layer_target(partition: &str, name: &str) -> PathBuf {
+    let rel = name.replace("%2F", "/").replace("%25", "%");
+    stock_root(partition).join(rel.trim_start_matches('/'))
+}
+
+fn create_layer(root: &Path, stock: &Path, rel: &Path) -> Result<PathBuf> {
+    create_mirrored(root, stock, Path::new(""))?;
+
+    let layer = root.join(layer_name(rel));
+    match fs::create_dir(&layer) {
+        Ok(()) => mirror_attrs(&stock.join(rel), &layer)?,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    Ok(layer)
+}
+
+pub fn layer_dirs(partition: &str, target: &str) -> Result<Option<(PathBuf, PathBuf)>> {
+    if !is_enabled(partition) {
+        return Ok(None);
+    }
+
+    let stock = stock_root(partition);
+    let Ok(rel) = Path::new(target).strip_prefix(&stock) else {
+        bail!(
+            "{} is outside {}, it cannot share the partition's writable layer",
+            target,
+            stock.display()
+        );
+    };
+    migrate_legacy(partition)?;
+    let dir = partition_dir(partition);
+
+    let upper = create_layer(&dir.join(UPPER_DIR), &stock, rel)
+        .with_context(|| format!("Failed to prepare upperdir for {}", target))?;
+    let work = create_layer(&dir.join(WORK_DIR), &stock, rel)
+        .with_context(|| format!("Failed to prepare workdir for {}", target))?;
+
+    Ok(Some((upper, work)))
+}
+
+fn live_layers(partition: &str) -> Vec<(String, PathBuf)> {
+    let upper_root = partition_dir(partition).join(UPPER_DIR);
+    let Ok(mounts) = Process::myself().and_then(|p| p.mountinfo()) else {
+        return Vec::new();
+    };
+
+    mounts
+        .into_iter()
+        .filter(|m| m.fs_type == "overlay")
+        .filter_map(|m| {
+            let upper = m.super_options.get("upperdir")?.as_deref()?;
+            Path::new(upper)
+                .starts_with(&upper_root)
+                .then(|| (m.mount_point.display().to_string(), PathBuf::from(upper)))
+        })
+        .collect()
+}
+
+fn writable_targets(partition: &str) -> Vec<String> {
+    let mut targets: Vec<String> = live_layers(partition)
+        .into_iter()
+        .map(|(target, _)| target)
+        .collect();
+
+    targets.sort();
+    targets.dedup();
+    targets
+}
+
+fn migrate_legacy(partition: &str) -> Result<()> {
+    let dir = partition_dir(partition);
+    let upper_root = dir.join(UPPER_DIR);
+    let legacy: Vec<_> = fs::read_dir(&upper_root)?
+        .flatten()
+        .filter(|e| !e.file_name().to_string_lossy().starts_with(LAYER_PREFIX))
+        .collect();
+    if legacy.is_empty() {
+        return Ok(());
+    }
+
+    if let Some((target, _)) = live_layers(partition)
+        .into_iter()
+        .find(|(_, upper)| *upper == upper_root)
+    {
+        bail!(
+            "{} still uses the old writable layout at {}; reboot once before using rw",
+            target,
+            upper_root.display()
+        );
+    }
+
+    log::info!(
+        "Migrating writable layer of {} to per-target layers",
+        partition
+    );
+    let layer = create_layer(&upper_root, &stock_root(partition), Path::new(""))?;
+    for entry in legacy {
+        fs::rename(entry.path(), layer.join(entry.file_name()))
+            .with_context(|| format!("Failed to migrate {}", entry.path().display()))?;
+    }
+
+    let work_root = dir.join(WORK_DIR);
+    for entry in fs::read_dir(&work_root)?.flatten() {
+        if !entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(LAYER_PREFIX)
+        {
+            fs::remove_dir_all(entry.path())
+                .with_context(|| format!("Failed to remove {}", entry.path().display()))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn prepare_roots(partition: &str) -> Result<()> {
+    let dir = partition_dir(partition);
+    let stock = stock_root(partition);
+
+    create_mirrored(&dir.join(UPPER_DIR), &stock, Path::new(""))?;
+    create_mirrored(&dir.join(WORK_DIR), &stock, Path::new(""))?;
+
+    Ok(())
+}
+
+pub fn enable(config: &Config, partition: &str) -> Result<()> {
+    if !partitions::names(&config.partitions)
+        .iter()
+        .any(|p| p == partition)
+    {
+        bail!("Unknown partition: {}", partition);
+    }
+    if !stock_root(partition).is_dir() {
+        bail!("Partition /{} does not exist on this device", partition);
+    }
+
+    prepare_roots(partition)
+        .with_context(|| format!("Failed to create writable layer for {}", partition))
+}
+
+pub fn status() -> Result<Vec<RwStatus>> {
+    let root = Path::new(defs::SYSTEM_RW_DIR);
+    if !root.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut result = Vec::new();
+
+    for entry in fs::read_dir(root)?.flatten() {
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let partition = entry.file_name().to_string_lossy().to_string();
+        if is_enabled(&partition)
+            && let Err(e) = migrate_legacy(&partition)
+        {
+            log::warn!("{:#}", e);
+        }
+
+        let mut entries = 0;
+        let mut bytes = 0;
+        for item in WalkDir::new(entry.path().join(UPPER_DIR))
+            .min_depth(2)
+            .into_iter()
+            .flatten()
+        {
+            entries += 1;
+            if item.file_type().is_file()
+                && let Ok(meta) = item.metadata()
+            {
+                bytes += meta.len();
+            }
+        }
+
+        result.push(RwStatus {
+            enabled: is_enabled(&partition),
+            mounted_targets: writable_targets(&partition),
+            partition,
+            entries,
+            bytes,
+        });
+    }
+
+    result.sort_by(|a, b| a.partition.cmp(&b.partition));
+
+    Ok(result)
+}
+
+pub fn diff(partition: &str) -> Result<Vec<RwChange>> {
+    if !is_enabled(partition) {
+        bail!("Writable overlay is not enabled for {}", partition);
+    }
+    migrate_legacy(partition)?;
+
+    let upper_root = partition_dir(partition).join(UPPER_DIR);
+    let mut layers: Vec<_> = fs::read_dir(&upper_root)?
+        .flatten()
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+        .collect();
+    layers.sort_by_key(|e| e.file_name());
+
+    let mut changes = Vec::new();
+
+    for layer in layers {
+        let upper = layer.path();
+        let target = layer_target(partition, &layer.file_name().to_string_lossy());
+
+        for entry in WalkDir::new(&upper)
+            .min_depth(1)
+            .sort_by_file_name()
+            .into_iter()
+            .flatten()
+        {
+            let Ok(rel) = entry.path().strip_prefix(&upper) else {
+                continue;
+            };
+            let file_type = entry.file_type();
+
+            let kind = if file_type.is_char_device() {
+                match entry.metadata() {
+                    Ok(meta) if meta.rdev() == 0 => ChangeKind::Deleted,
+                    _ => ChangeKind::Written,
+                }
+            } else if file_type.is_dir() {
+                match lgetxattr(entry.path(), OVERLAY_OPAQUE_XATTR) {
+                    Ok(value) if value == b"y" => ChangeKind::Opaque,
+                    _ => continue,
+                }
+            } else {
+                ChangeKind::Written
+            };
+
+            changes.push(RwChange {
+                path: target.join(rel).display().to_string(),
+                kind,
+            });
+        }
+    }
+
+    Ok(changes)
+}
+
+pub fn reset(partition: &str, force: bool) -> Result<()> {
+    if !is_enabled(partition) {
+        bail!("Writable overlay is not enabled for {}", partition);
+    }
+
+    let mut mounted = writable_targets(partition);
+    if !mounted.is_empty() && !force {
+        bail!(
+            "{} has writable overlays mounted ({}); reset them after reboot or pass --force",
+            partition,
+            mounted.join(", ")
+        );
+    }
+
+    mounted.sort_by_key(|target| std::cmp::Reverse(Path::new(target).components().count()));
+    for target in &mounted {
+        log::info!("Unmounting writable overlay {} before reset", target);
+        unmount(target.as_str(), UnmountFlags::DETACH)
+            .with_context(|| format!("Failed to unmount {} (still in use?)", target))?;
+    }
+    let remaining = writable_targets(partition);
+    if !remaining.is_empty() {
+        bail!(
+            "{} still has writable overlays mounted ({})",
+            partition,
+            remaining.join(", ")
+        );
+    }
+
+    let dir = partition_dir(partition);
+    for name in [UPPER_DIR, WORK_DIR] {
+        let path = dir.join(name);
+        fs::remove_dir_all(&path)
+            .with_context(|| format!("Failed to remove {}", path.display()))?;
+    }
+
+    prepare_roots(partition)
+        .with_context(|| format!("Failed to recreate writable layer for {}", partition))
+}
+
+pub fn export(output: &Path, partition: Option<&str>) -> Result<()> {
+    let partitions: Vec<String> = match partition {
+        Some(p) if is_enabled(p) => vec![p.to_string()],
+        Some(p) => bail!("Writable overlay is not enabled for {}", p),
+        None => status()?
+            .into_iter()
+            .filter(|s| s.enabled)
+            .map(|s| s.partition)
+            .collect(),
+    };
+
+    if partitions.is_empty() {
+        bail!("No writable overlays to export");
+    }
+
+    if output.exists() && fs::read_dir(output)?.next().is_some() {
+        bail!("{} already exists and is not empty", output.display());
+    }
+
+    for partition in &partitions {
+        migrate_legacy(partition)?;
+        sync_dir(
+            &partition_dir(partition).join(UPPER_DIR),
+            &output.join(partition),
+            false,
+        )
+        .with_context(|| format!("Failed to export writable layer of {}", partition))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layer_name_round_trips_through_layer_target() {
+        for rel in [
+            "",
+            "etc",
+            "etc/init",
+            "app/Foo%2FBar",
+            "priv-app/100%",
+            "fonts/%25/%",
+        ] {
+            let name = layer_name(Path::new(rel));
+            assert!(name.starts_with(LAYER_PREFIX), "{name}");
+            assert!(!name.contains('/'), "{name}");
+            assert_eq!(
+                layer_target("system", &name),
+                Path::new("/system").join(rel),
+                "{rel}"
+            );
+        }
+    }
+
+    #[test]
+    fn layer_names_of_nested_targets_differ() {
+        assert_ne!(
+            layer_name(Path::new("etc/init")),
+            layer_name(Path::new("etc"))
+        );
+        assert_ne!(
+            layer_name(Path::new("etc/init")),
+            layer_name(Path::new("etc%2Finit"))
+        );
+    }
+}
@@ -7,7 +7,16 @@ use std::{
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
-use crate::{defs, sys::fs::xattr};
+use crate::{
+    core::{
+        ops::executor::{MountTiming, TargetRecord},
+        verify::VerifyReport,
+    },
+    defs,
+    mount::{hardening::HardenedMount, magic_mount::MagicMountReport},
+    sys::capabilities::{self, KernelCapabilities},
+    utils::ToolInvocation,
+};
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct RuntimeState {
@@ -19,8 +28,31 @@ pub struct RuntimeState {
     pub magic_modules: Vec<String>,
     #[serde(default)]
     pub active_mounts: Vec<String>,
+    #[serde(flatten)]
+    pub capabilities: KernelCapabilities,
+    #[serde(default)]
+    pub writable_mounts: Vec<String>,
+    #[serde(default)]
+    pub targets: Vec<TargetRecord>,
+    #[serde(default)]
+    pub mount_timing: MountTiming,
+    #[serde(default)]
+    pub hardened_mounts: Vec<HardenedMount>,
+    #[serde(default)]
+    pub isolated: Vec<IsolatedNamespace>,
     #[serde(default)]
-    pub tmpfs_xattr_supported: bool,
+    pub verification: VerifyReport,
+    #[serde(default)]
+    pub magic_report: MagicMountReport,
+    #[serde(default)]
+    pub tool_invocations: Vec<ToolInvocation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IsolatedNamespace {
+    pub module: String,
+    pub pin: PathBuf,
+    pub targets: Vec<TargetRecord>,
 }
 
 impl RuntimeState {
@@ -41,8 +73,6 @@ impl RuntimeState {
 
         let pid = std::process::id();
 
-        let tmpfs_xattr_supported = xattr::is_overlay_xattr_supported().unwrap_or(false);
-
         Self {
             timestamp,
             pid,
@@ -51,7 +81,15 @@ impl RuntimeState {
             overlay_modules,
             magic_modules,
             active_mounts,
-            tmpfs_xattr_supported,
+            capabilities: capabilities::probe().clone(),
+            writable_mounts: Vec::new(),
+            targets: Vec::new(),
+            mount_timing: MountTiming::default(),
+            hardened_mounts: Vec::new(),
+            isolated: Vec::new(),
+            verification: VerifyReport::default(),
+            magic_report: MagicMountReport::default(),
+            tool_invocations: Vec::new(),
         }
     }
 
@@ -0,0 +1,182 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use procfs::process::{MountInfo, Process};
+use serde::Serialize;
+
+use crate::{
+    core::{
+        ops::executor::{Mechanism, TargetRecord},
+        state::RuntimeState,
+    },
+    sys::partitions,
+};
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetHealth {
+    Ok,
+    Missing,
+    Shadowed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TargetStatus {
+    pub target: String,
+    pub mechanism: Mechanism,
+    pub modules: Vec<String>,
+    pub health: TargetHealth,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub shadowed_by: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExtraMount {
+    pub mount_point: String,
+    pub fs_type: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatusReport {
+    pub drift: bool,
+    pub targets: Vec<TargetStatus>,
+    pub extra: Vec<ExtraMount>,
+    pub affected_modules: Vec<String>,
+}
+
+fn ancestry(mounts: &[MountInfo], idx: usize) -> Vec<i32> {
+    let by_id: HashMap<i32, &MountInfo> = mounts.iter().map(|m| (m.mnt_id, m)).collect();
+    let mut chain = Vec::new();
+    let mut current = mounts[idx].pid;
+
+    while let Some(parent) = by_id.get(&current) {
+        if chain.contains(&parent.mnt_id) {
+            break;
+        }
+        chain.push(parent.mnt_id);
+        current = parent.pid;
+    }
+
+    chain
+}
+
+fn shadowing(mounts: &[MountInfo], idx: usize) -> Vec<String> {
+    let own = &mounts[idx];
+    let chain = ancestry(mounts, idx);
+
+    mounts[idx + 1..]
+        .iter()
+        .filter(|m| own.mount_point.starts_with(&m.mount_point))
+        .filter(|m| !chain.contains(&m.mnt_id))
+        .map(|m| m.mount_point.display().to_string())
+        .collect()
+}
+
+fn inspect(mounts: &[MountInfo], state: &RuntimeState, record: &TargetRecord) -> TargetStatus {
+    let target = Path::new(&record.target);
+    let own: Vec<Option<usize>> = match record.mechanism {
+        Mechanism::Overlay => vec![
+            mounts
+                .iter()
+                .rposition(|m| m.mount_point == target && m.fs_type == "overlay"),
+        ],
+        _ => state
+            .magic_report
+            .mount_points
+            .iter()
+            .filter(|p| p.path.starts_with(target))
+            .map(|p| mounts.iter().rposition(|m| m.mount_point == p.path))
+            .collect(),
+    };
+
+    let (health, shadowed_by) = match own.into_iter().collect::<Option<Vec<usize>>>() {
+        Some(found) if !found.is_empty() => {
+            let mut shadowed_by: Vec<String> = found
+                .into_iter()
+                .flat_map(|idx| shadowing(mounts, idx))
+                .collect();
+            shadowed_by.sort();
+            shadowed_by.dedup();
+            if shadowed_by.is_empty() {
+                (TargetHealth::Ok, shadowed_by)
+            } else {
+                (TargetHealth::Shadowed, shadowed_by)
+            }
+        }
+        _ => (TargetHealth::Missing, Vec::new()),
+    };
+
+    TargetStatus {
+        target: record.target.clone(),
+        mechanism: record.mechanism,
+        modules: record.modules.clone(),
+        health,
+        shadowed_by,
+    }
+}
+
+fn extra_mounts(
+    mounts: &[MountInfo],
+    state: &RuntimeState,
+    mount_source: &str,
+    extra_partitions: &[String],
+) -> Vec<ExtraMount> {
+    let partitions: Vec<String> = partitions::names(extra_partitions)
+        .into_iter()
+        .map(|p| format!("/{}", p))
+        .collect();
+
+    mounts
+        .iter()
+        .filter(|m| {
+            matches!(m.fs_type.as_str(), "overlay" | "tmpfs")
+                && m.mount_source.as_deref() == Some(mount_source)
+        })
+        .filter(|m| partitions.iter().any(|p| m.mount_point.starts_with(p)))
+        .filter(|m| {
+            !state
+                .targets
+                .iter()
+                .any(|t| m.mount_point.starts_with(&t.target))
+        })
+        .map(|m| ExtraMount {
+            mount_point: m.mount_point.display().to_string(),
+            fs_type: m.fs_type.clone(),
+        })
+        .collect()
+}
+
+pub fn check(
+    state: &RuntimeState,
+    mount_source: &str,
+    extra_partitions: &[String],
+) -> Result<StatusReport> {
+    let mounts = Process::myself()
+        .and_then(|p| p.mountinfo())
+        .context("Failed to read mountinfo")?
+        .0;
+
+    let targets: Vec<TargetStatus> = state
+        .targets
+        .iter()
+        .filter(|t| t.mechanism != Mechanism::Failed)
+        .map(|t| inspect(&mounts, state, t))
+        .collect();
+    let extra = extra_mounts(&mounts, state, mount_source, extra_partitions);
+
+    let affected_modules: BTreeSet<String> = targets
+        .iter()
+        .filter(|t| t.health != TargetHealth::Ok)
+        .flat_map(|t| t.modules.iter().cloned())
+        .collect();
+
+    Ok(StatusReport {
+        drift: !extra.is_empty() || targets.iter().any(|t| t.health != TargetHealth::Ok),
+        targets,
+        extra,
+        affected_modules: affected_modules.into_iter().collect(),
+    })
+}
@@ -1,11 +1,12 @@
 use std::{
+    ffi::OsStr,
     fs,
     os::unix::fs::PermissionsExt,
     path::{Path, PathBuf},
-    process::{Command, Stdio},
+    time::Duration,
 };
 
-use anyhow::{Context, Result, bail, ensure};
+use anyhow::{Context, Result, bail};
 use jwalk::WalkDir;
 use loopdev::LoopControl;
 use rustix::mount::{
@@ -15,7 +16,11 @@ use rustix::mount::{
 #[cfg(any(target_os = "linux", target_os = "android"))]
 use crate::mount::umount_mgr::send_umountable;
 use crate::{
-    core::backend::StorageBackend,
+    core::{
+        backend::StorageBackend,
+        capacity::{self, Estimate, FsParams},
+        inventory::Module,
+    },
     defs,
     mount::overlayfs::utils as overlay_utils,
     sys::{
@@ -27,6 +32,9 @@ use crate::{
 };
 
 const DEFAULT_SELINUX_CONTEXT: &str = "u:object_r:system_file:s0";
+const MKFS_TIMEOUT: Duration = Duration::from_secs(120);
+const FSCK_TIMEOUT: Duration = Duration::from_secs(120);
+const MKFS_EROFS_TIMEOUT: Duration = Duration::from_secs(600);
 
 pub struct StorageHandle {
     pub backend: Box<dyn StorageBackend>,
@@ -44,6 +52,10 @@ impl StorageHandle {
     pub fn mode(&self) -> &str {
         self.backend.mode()
     }
+
+    pub fn report_usage(&self) {
+        self.backend.report_usage()
+    }
 }
 
 pub struct ErofsBackend {
@@ -84,6 +96,7 @@ impl StorageBackend for ErofsBackend {
 pub struct Ext4Backend {
     pub mount_point: PathBuf,
     pub mode: String,
+    pub estimate: Estimate,
 }
 
 impl StorageBackend for Ext4Backend {
@@ -98,6 +111,10 @@ impl StorageBackend for Ext4Backend {
     fn mode(&self) -> &str {
         &self.mode
     }
+
+    fn report_usage(&self) {
+        capacity::log_usage(FsParams::EXT4, &self.estimate, &self.mount_point);
+    }
 }
 
 pub struct TmpfsBackend {
@@ -119,20 +136,39 @@ impl StorageBackend for TmpfsBackend {
     }
 }
 
-fn calculate_total_size(path: &Path) -> Result<u64> {
-    let mut total_size = 0;
-    if path.is_dir() {
-        for entry in fs::read_dir(path)? {
-            let entry = entry?;
-            let file_type = entry.file_type()?;
-            if file_type.is_file() {
-                total_size += entry.metadata()?.len();
-            } else if file_type.is_dir() {
-                total_size += calculate_total_size(&entry.path())?;
-            }
-        }
+pub struct AttachedBackend {
+    pub mount_point: PathBuf,
+    pub mode: String,
+}
+
+impl StorageBackend for AttachedBackend {
+    fn commit(&mut self, _disable_umount: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn mount_point(&self) -> &Path {
+        &self.mount_point
+    }
+
+    fn mode(&self) -> &str {
+        &self.mode
     }
-    Ok(total_size)
+}
+
+pub fn attach(mount_point: &Path, mode: &str) -> Result<StorageHandle> {
+    if !is_mounted(mount_point) {
+        bail!(
+            "No module storage is mounted at {}, mount globally first",
+            mount_point.display()
+        );
+    }
+
+    Ok(StorageHandle {
+        backend: Box::new(AttachedBackend {
+            mount_point: mount_point.to_path_buf(),
+            mode: mode.to_string(),
+        }),
+    })
 }
 
 fn check_image<P>(img: P) -> Result<()>
@@ -140,19 +176,18 @@ where
     P: AsRef<Path>,
 {
     let path = img.as_ref();
-    let path_str = path.to_str().context("Invalid path string")?;
-    let result = Command::new("e2fsck")
-        .args(["-yf", path_str])
-        .status()
-        .with_context(|| format!("Failed to exec e2fsck {}", path.display()))?;
-    let _ = result.code();
+    let outcome = utils::run_e2fsck([OsStr::new("-yf"), path.as_os_str()], FSCK_TIMEOUT)
+        .with_context(|| format!("Failed to check image {}", path.display()))?;
+    if outcome != utils::FsckOutcome::Clean {
+        log::warn!("e2fsck repaired {}: {:?}", path.display(), outcome);
+    }
     Ok(())
 }
 
 pub fn setup(
     mnt_base: &Path,
     img_path: &Path,
-    moduledir: &Path,
+    modules: &[Module],
     force_ext4: bool,
     use_erofs: bool,
     mount_source: &str,
@@ -183,7 +218,7 @@ pub fn setup(
         let _ = mount_change(path, MountPropagationFlags::PRIVATE);
     };
 
-    if use_erofs && is_erofs_supported() {
+    if use_erofs && crate::sys::capabilities::probe().erofs {
         let erofs_path = img_path.with_extension("erofs");
         let staging_dir = Path::new(defs::RUN_DIR).join("erofs_staging");
 
@@ -221,7 +256,7 @@ pub fn setup(
         });
     }
 
-    let handle = setup_ext4_image(mnt_base, img_path, moduledir)?;
+    let handle = setup_ext4_image(mnt_base, img_path, modules)?;
     make_private(mnt_base);
     try_hide(mnt_base);
 
@@ -241,21 +276,34 @@ fn try_setup_tmpfs(target: &Path, mount_source: &str) -> Result<bool> {
     Ok(false)
 }
 
-fn setup_ext4_image(target: &Path, img_path: &Path, moduledir: &Path) -> Result<Ext4Backend> {
-    let total_size = calculate_total_size(moduledir)?;
-    let min_size = 64 * 1024 * 1024;
-    let grow_size = std::cmp::max((total_size as f64 * 1.2) as u64, min_size);
-
-    fs::File::create(img_path)?.set_len(grow_size)?;
-
-    let result = Command::new("mkfs.ext4")
-        .arg("-b")
-        .arg("1024")
-        .arg(img_path)
-        .stdout(std::process::Stdio::piped())
-        .output()?;
-
-    ensure!(result.status.success(), "Failed to format ext4 image");
+fn setup_ext4_image(target: &Path, img_path: &Path, modules: &[Module]) -> Result<Ext4Backend> {
+    let estimate = capacity::estimate(modules, FsParams::EXT4);
+    capacity::log_estimate(FsParams::EXT4, &estimate);
+    let (image_size, inode_count) = capacity::ext4_layout(&estimate);
+    log::info!(
+        "sizing ext4 image: {} bytes, {} inodes",
+        image_size,
+        inode_count
+    );
+
+    fs::File::create(img_path)?.set_len(image_size)?;
+
+    utils::run_checked(
+        "mkfs.ext4",
+        [
+            OsStr::new("-b"),
+            OsStr::new(&FsParams::EXT4.block_size.to_string()),
+            OsStr::new("-I"),
+            OsStr::new(&FsParams::EXT4.inode_size.to_string()),
+            OsStr::new("-N"),
+            OsStr::new(&inode_count.to_string()),
+            OsStr::new("-m"),
+            OsStr::new("0"),
+            img_path.as_os_str(),
+        ],
+        MKFS_TIMEOUT,
+    )
+    .context("Failed to format ext4 image")?;
 
     check_image(img_path)?;
     let _ = lsetfilecon(img_path, "u:object_r:ksu_file:s0");
@@ -284,37 +332,31 @@ fn setup_ext4_image(target: &Path, img_path: &Path, moduledir: &Path) -> Result<
     Ok(Ext4Backend {
         mount_point: target.to_path_buf(),
         mode: "ext4".to_string(),
+        estimate,
     })
 }
 
-fn is_erofs_supported() -> bool {
-    fs::read_to_string("/proc/filesystems")
-        .map(|content| content.contains("erofs"))
-        .unwrap_or(false)
-}
-
 fn create_erofs_image(src_dir: &Path, image_path: &Path) -> Result<()> {
     let mkfs_bin = Path::new(defs::MKFS_EROFS_PATH);
     let cmd_name = if mkfs_bin.exists() {
         mkfs_bin.as_os_str()
     } else {
-        std::ffi::OsStr::new("mkfs.erofs")
+        OsStr::new("mkfs.erofs")
     };
 
-    let output = Command::new(cmd_name)
-        .arg("-z")
-        .arg("lz4hc")
-        .arg("-x")
-        .arg("256")
-        .arg(image_path)
-        .arg(src_dir)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()?;
-
-    if !output.status.success() {
-        bail!("Failed to create EROFS image");
-    }
+    utils::run_checked(
+        cmd_name,
+        [
+            OsStr::new("-z"),
+            OsStr::new("lz4hc"),
+            OsStr::new("-x"),
+            OsStr::new("256"),
+            image_path.as_os_str(),
+            src_dir.as_os_str(),
+        ],
+        MKFS_EROFS_TIMEOUT,
+    )
+    .context("Failed to create EROFS image")?;
 
     let _ = fs::set_permissions(image_path, fs::Permissions::from_mode(0o644));
     let _ = lsetfilecon(image_path, "u:object_r:ksu_file:s0");
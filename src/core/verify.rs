@@ -0,0 +1,421 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    fs,
+    io::{self, Read},
+    os::unix::fs::{FileTypeExt, MetadataExt},
+    path::{Path, PathBuf},
+};
+
+use extattr::lgetxattr;
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::{
+    conf::config::{Config, VerifyMode},
+    core::{
+        inventory::removals,
+        ops::{
+            executor::{ExecutionResult, Mechanism},
+            planner::MountPlan,
+        },
+    },
+    defs::{REPLACE_DIR_FILE_NAME, REPLACE_DIR_XATTR},
+    sys::partitions,
+};
+
+const SAMPLE_LIMIT: usize = 64;
+const REPORTED_ENTRIES: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Expected {
+    File,
+    Symlink,
+    Directory,
+    Whiteout,
+    Opaque,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifyMismatch {
+    pub target: String,
+    pub path: String,
+    pub module: String,
+    pub expected: Expected,
+    pub actual: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VerifyReport {
+    pub mode: VerifyMode,
+    pub checked: usize,
+    pub mismatches: Vec<VerifyMismatch>,
+}
+
+struct Layer {
+    module: String,
+    root: PathBuf,
+}
+
+struct Expectation {
+    rel: PathBuf,
+    kind: Expected,
+    sources: Vec<(String, PathBuf)>,
+}
+
+fn source_layer(storage_root: &Path, storage_view: &Path, lowerdir: &Path) -> Option<Layer> {
+    let rel = lowerdir.strip_prefix(storage_root).ok()?;
+    let module = rel.iter().next()?.to_string_lossy().to_string();
+
+    Some(Layer {
+        root: storage_view.join(rel),
+        module,
+    })
+}
+
+fn is_opaque(path: &Path) -> bool {
+    matches!(lgetxattr(path, REPLACE_DIR_XATTR), Ok(v) if v == b"y")
+        || path.join(REPLACE_DIR_FILE_NAME).exists()
+}
+
+fn classify(path: &Path, meta: &fs::Metadata) -> Expected {
+    let file_type = meta.file_type();
+    if file_type.is_char_device() && meta.rdev() == 0 {
+        Expected::Whiteout
+    } else if file_type.is_symlink() {
+        Expected::Symlink
+    } else if file_type.is_dir() {
+        if is_opaque(path) {
+            Expected::Opaque
+        } else {
+            Expected::Directory
+        }
+    } else {
+        Expected::File
+    }
+}
+
+fn expectations(layers: &[Layer]) -> (Vec<Expectation>, HashMap<PathBuf, HashSet<OsString>>) {
+    let mut index: HashMap<PathBuf, usize> = HashMap::new();
+    let mut expected: Vec<Expectation> = Vec::new();
+    let mut names: HashMap<PathBuf, HashSet<OsString>> = HashMap::new();
+    let mut removed: Vec<PathBuf> = Vec::new();
+    let mut opaque: Vec<PathBuf> = Vec::new();
+
+    for layer in layers {
+        let mut layer_removed = Vec::new();
+        let mut layer_opaque = Vec::new();
+
+        for entry in WalkDir::new(&layer.root)
+            .min_depth(1)
+            .sort_by_file_name()
+            .into_iter()
+            .flatten()
+        {
+            let Ok(rel) = entry.path().strip_prefix(&layer.root) else {
+                continue;
+            };
+            if rel.file_name().is_some_and(|n| n == REPLACE_DIR_FILE_NAME)
+                || removed.iter().any(|p| rel.starts_with(p))
+                || opaque
+                    .iter()
+                    .any(|p| rel.starts_with(p) && rel != p.as_path())
+            {
+                continue;
+            }
+            let Ok(meta) = entry.metadata() else {
+                continue;
+            };
+
+            let marker = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| removals::marker_target(name, &meta));
+            let (rel, kind) = match marker {
+                Some(target) => (rel.with_file_name(target), Expected::Whiteout),
+                None => (rel.to_path_buf(), classify(entry.path(), &meta)),
+            };
+            let rel = rel.as_path();
+            match kind {
+                Expected::Whiteout => layer_removed.push(rel.to_path_buf()),
+                Expected::Opaque => layer_opaque.push(rel.to_path_buf()),
+                _ => {}
+            }
+            if kind != Expected::Whiteout
+                && let Some(parent) = rel.parent()
+            {
+                names
+                    .entry(parent.to_path_buf())
+                    .or_default()
+                    .insert(entry.file_name().to_os_string());
+            }
+
+            let source = (layer.module.clone(), entry.path().to_path_buf());
+            match index.get(rel) {
+                Some(&idx) => expected[idx].sources.push(source),
+                None => {
+                    index.insert(rel.to_path_buf(), expected.len());
+                    expected.push(Expectation {
+                        rel: rel.to_path_buf(),
+                        kind,
+                        sources: vec![source],
+                    });
+                }
+            }
+        }
+
+        removed.extend(layer_removed);
+        opaque.extend(layer_opaque);
+    }
+
+    (expected, names)
+}
+
+fn same_content(a: &Path, b: &Path) -> io::Result<bool> {
+    let (meta_a, meta_b) = (fs::metadata(a)?, fs::metadata(b)?);
+    if meta_a.dev() == meta_b.dev() && meta_a.ino() == meta_b.ino() {
+        return Ok(true);
+    }
+    if meta_a.len() != meta_b.len() {
+        return Ok(false);
+    }
+
+    let (mut file_a, mut file_b) = (fs::File::open(a)?, fs::File::open(b)?);
+    let mut buf_a = vec![0u8; 64 * 1024];
+    let mut buf_b = vec![0u8; 64 * 1024];
+    loop {
+        let read = file_a.read(&mut buf_a)?;
+        if read == 0 {
+            return Ok(true);
+        }
+        file_b.read_exact(&mut buf_b[..read])?;
+        if buf_a[..read] != buf_b[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+fn describe(meta: &fs::Metadata) -> String {
+    let file_type = meta.file_type();
+    if file_type.is_symlink() {
+        "symlink"
+    } else if file_type.is_dir() {
+        "directory"
+    } else if file_type.is_file() {
+        "file"
+    } else {
+        "special file"
+    }
+    .to_string()
+}
+
+fn check(
+    path: &Path,
+    expectation: &Expectation,
+    sources: &[(String, PathBuf)],
+    names: &HashMap<PathBuf, HashSet<OsString>>,
+) -> Option<String> {
+    let meta = match fs::symlink_metadata(path) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return (expectation.kind != Expected::Whiteout).then(|| "missing".to_string());
+        }
+        Err(e) => return Some(format!("unreadable: {}", e)),
+    };
+
+    match expectation.kind {
+        Expected::Whiteout => Some(format!("stock {} still visible", describe(&meta))),
+        Expected::Directory if !meta.is_dir() => Some(describe(&meta)),
+        Expected::Directory => None,
+        Expected::Opaque if !meta.is_dir() => Some(describe(&meta)),
+        Expected::Opaque => {
+            let allowed = names.get(&expectation.rel);
+            let leaked: Vec<String> = fs::read_dir(path)
+                .map(|entries| {
+                    entries
+                        .flatten()
+                        .map(|e| e.file_name())
+                        .filter(|n| !allowed.is_some_and(|a| a.contains(n)))
+                        .map(|n| n.to_string_lossy().to_string())
+                        .collect()
+                })
+                .unwrap_or_default();
+            (!leaked.is_empty()).then(|| {
+                format!(
+                    "stock entries visible: {}",
+                    leaked
+                        .iter()
+                        .take(REPORTED_ENTRIES)
+                        .cloned()
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+        }
+        Expected::Symlink => {
+            let actual = fs::read_link(path).ok();
+            if sources
+                .iter()
+                .any(|(_, src)| fs::read_link(src).ok() == actual)
+            {
+                None
+            } else {
+                Some(match actual {
+                    Some(target) => format!("symlink to {}", target.display()),
+                    None => describe(&meta),
+                })
+            }
+        }
+        Expected::File if !meta.is_file() => Some(describe(&meta)),
+        Expected::File => {
+            let Some((_, src)) = sources
+                .iter()
+                .find(|(_, src)| same_content(path, src).unwrap_or(false))
+            else {
+                return Some("content differs".to_string());
+            };
+            let src_meta = fs::metadata(src).ok()?;
+            (src_meta.mode() != meta.mode()
+                || src_meta.uid() != meta.uid()
+                || src_meta.gid() != meta.gid())
+            .then(|| {
+                format!(
+                    "attributes {:o} {}:{}, expected {:o} {}:{}",
+                    meta.mode() & 0o7777,
+                    meta.uid(),
+                    meta.gid(),
+                    src_meta.mode() & 0o7777,
+                    src_meta.uid(),
+                    src_meta.gid()
+                )
+            })
+        }
+    }
+}
+
+fn verify_target(
+    target: &Path,
+    layers: &[Layer],
+    first_wins: bool,
+    mode: &VerifyMode,
+    report: &mut VerifyReport,
+) {
+    let (mut expected, names) = expectations(layers);
+    if *mode == VerifyMode::Sample && expected.len() > SAMPLE_LIMIT {
+        fastrand::shuffle(&mut expected);
+        expected.truncate(SAMPLE_LIMIT);
+    }
+
+    for expectation in &expected {
+        let sources = if first_wins {
+            &expectation.sources[..1]
+        } else {
+            &expectation.sources[..]
+        };
+        let path = target.join(&expectation.rel);
+
+        report.checked += 1;
+        if let Some(actual) = check(&path, expectation, sources, &names) {
+            report.mismatches.push(VerifyMismatch {
+                target: target.display().to_string(),
+                path: path.display().to_string(),
+                module: sources[0].0.clone(),
+                expected: expectation.kind,
+                actual,
+            });
+        }
+    }
+}
+
+fn magic_layers(config: &Config, storage_view: &Path, module_id: &str) -> Vec<(PathBuf, Layer)> {
+    let root = storage_view.join(module_id);
+    partitions::resolve(&config.partitions)
+        .into_iter()
+        .filter(|p| root.join(&p.name).is_dir())
+        .map(|p| {
+            (
+                p.mount_root(),
+                Layer {
+                    module: module_id.to_string(),
+                    root: root.join(&p.name),
+                },
+            )
+        })
+        .collect()
+}
+
+pub fn run(
+    config: &Config,
+    plan: &MountPlan,
+    result: &ExecutionResult,
+    storage_root: &Path,
+    storage_view: &Path,
+) -> VerifyReport {
+    let mut report = VerifyReport {
+        mode: config.verify_mode.clone(),
+        ..Default::default()
+    };
+    if config.verify_mode == VerifyMode::Off {
+        return report;
+    }
+
+    for record in &result.targets {
+        if record.mechanism == Mechanism::Failed {
+            continue;
+        }
+        let Some(op) = plan
+            .overlay_ops
+            .iter()
+            .find(|op| op.target == record.target)
+        else {
+            continue;
+        };
+        let layers: Vec<Layer> = op
+            .lowerdirs
+            .iter()
+            .filter_map(|lowerdir| source_layer(storage_root, storage_view, lowerdir))
+            .collect();
+
+        verify_target(
+            Path::new(&record.target),
+            &layers,
+            record.mechanism == Mechanism::Overlay,
+            &config.verify_mode,
+            &mut report,
+        );
+    }
+
+    let mut magic: HashMap<PathBuf, Vec<Layer>> = HashMap::new();
+    for id in plan
+        .magic_module_ids
+        .iter()
+        .filter(|id| result.magic_module_ids.contains(id))
+    {
+        for (target, layer) in magic_layers(config, storage_view, id) {
+            magic.entry(target).or_default().push(layer);
+        }
+    }
+    for (target, layers) in magic {
+        verify_target(&target, &layers, false, &config.verify_mode, &mut report);
+    }
+
+    if report.mismatches.is_empty() {
+        log::info!(
+            "mount verification passed ({} paths checked)",
+            report.checked
+        );
+    } else {
+        for mismatch in &report.mismatches {
+            log::warn!(
+                "verification mismatch on {} ({}): expected {:?}, found {}",
+                mismatch.path,
+                mismatch.module,
+                mismatch.expected,
+                mismatch.actual
+            );
+        }
+    }
+
+    report
+}
@@ -1,6 +1,10 @@
 pub const MODULES_IMG_FILE: &str = "/data/adb/hybrid-mount/modules.img";
 pub const RUN_DIR: &str = "/data/adb/hybrid-mount/run/";
 pub const STATE_FILE: &str = "/data/adb/hybrid-mount/run/daemon_state.json";
+pub const MAGIC_TREE_FILE: &str = "/data/adb/hybrid-mount/run/magic_tree.json";
+pub const MODULE_STATS_CACHE: &str = "/data/adb/hybrid-mount/run/module_stats.json";
+pub const NAMESPACE_DIR: &str = "/data/adb/hybrid-mount/run/ns";
+pub const APEX_INFO_LIST: &str = "/apex/apex-info-list.xml";
 pub const DISABLE_FILE_NAME: &str = "disable";
 pub const REMOVE_FILE_NAME: &str = "remove";
 pub const SKIP_MOUNT_FILE_NAME: &str = "skip_mount";
@@ -64,5 +68,10 @@ pub const IGNORE_UNOUNT_PARTITIONS: &[&str] = &[
     "/system/lib64",
 ];
 
+pub const FORBIDDEN_TARGET_PREFIXES: &[&str] = &["/data", "/proc", "/sys", "/dev"];
+
 pub const REPLACE_DIR_FILE_NAME: &str = ".replace";
 pub const REPLACE_DIR_XATTR: &str = "trusted.overlay.opaque";
+pub const REMOVE_LIST_FILE_NAME: &str = "hybrid_remove.list";
+pub const REMOVE_MARKER_SUFFIX: &str = ".remove";
+pub const ATTRS_FILE_NAME: &str = "hybrid_attrs.json";
@@ -80,12 +80,22 @@ fn main() -> Result<()> {
             Commands::Modules => cli_handlers::handle_modules(&cli)?,
             Commands::Conflicts => cli_handlers::handle_conflicts(&cli)?,
             Commands::Diagnostics => cli_handlers::handle_diagnostics(&cli)?,
+            Commands::Capabilities { probe } => cli_handlers::handle_capabilities(*probe)?,
+            Commands::Status => cli_handlers::handle_status(&cli)?,
+            Commands::MagicTree { path, json } => {
+                cli_handlers::handle_magic_tree(path.as_deref(), *json)?
+            }
+            Commands::Rw { action } => cli_handlers::handle_rw(&cli, action)?,
+            Commands::Enter { module, command } => cli_handlers::handle_enter(module, command)?,
         }
 
         return Ok(());
     }
 
-    let config = load_final_config(&cli)?;
+    let mut config = load_final_config(&cli)?;
+    if cli.pid.is_some() {
+        config.disable_umount = true;
+    }
 
     utils::init_logging().context("Failed to initialize logging")?;
 
@@ -105,7 +115,7 @@ fn main() -> Result<()> {
 
     utils::check_ksu();
 
-    if config.disable_umount {
+    if config.disable_umount && cli.pid.is_none() {
         log::warn!("!! Umount is DISABLED via config.");
     }
 
@@ -115,14 +125,19 @@ fn main() -> Result<()> {
     sys::fs::ensure_dir_exists(&mnt_base)?;
 
     MountController::new(config, &mnt_base)
+        .select_namespace(cli.pid)
+        .context("Failed to select target mount namespace")?
         .init_storage(&mnt_base, &img_path)
         .context("Failed to initialize storage")?
         .scan_and_sync()
         .context("Failed to scan and sync modules")?
+        .select_module(cli.only_module.as_deref())?
         .generate_plan()
         .context("Failed to generate mount plan")?
         .execute()
         .context("Failed to execute mount plan")?
+        .verify()
+        .context("Failed to verify mounts")?
         .finalize()
         .context("Failed to finalize boot sequence")?;
 
@@ -0,0 +1,225 @@
+use std::{
+    ffi::CString,
+    io,
+    os::unix::ffi::OsStrExt,
+    path::Path,
+    sync::{LazyLock, Mutex},
+};
+
+use procfs::process::Process;
+use rustix::mount::{MountAttrFlags, MountFlags, mount_remount};
+use serde::{Deserialize, Serialize};
+
+use crate::conf::config::HardeningPolicy;
+
+static POLICY: LazyLock<Mutex<HardeningPolicy>> =
+    LazyLock::new(|| Mutex::new(HardeningPolicy::default()));
+static RECORDS: LazyLock<Mutex<Vec<HardenedMount>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HardenedMount {
+    pub target: String,
+    pub attributes: Vec<String>,
+    pub method: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[repr(C)]
+struct MountAttr {
+    attr_set: u64,
+    attr_clr: u64,
+    propagation: u64,
+    userns_fd: u64,
+}
+
+const ATTRIBUTE_NAMES: &[(MountAttrFlags, MountFlags, &str)] = &[
+    (MountAttrFlags::MOUNT_ATTR_RDONLY, MountFlags::RDONLY, "ro"),
+    (
+        MountAttrFlags::MOUNT_ATTR_NOSUID,
+        MountFlags::NOSUID,
+        "nosuid",
+    ),
+    (MountAttrFlags::MOUNT_ATTR_NODEV, MountFlags::NODEV, "nodev"),
+    (
+        MountAttrFlags::MOUNT_ATTR_NOEXEC,
+        MountFlags::NOEXEC,
+        "noexec",
+    ),
+    (
+        MountAttrFlags::MOUNT_ATTR_NOSYMFOLLOW,
+        MountFlags::NOSYMFOLLOW,
+        "nosymfollow",
+    ),
+];
+
+pub fn configure(policy: &HardeningPolicy) {
+    if let Ok(mut current) = POLICY.lock() {
+        *current = policy.clone();
+    }
+}
+
+fn attributes_for(policy: &HardeningPolicy, target: &Path, writable: bool) -> MountAttrFlags {
+    let mut attrs = MountAttrFlags::empty();
+
+    if policy.read_only && !writable {
+        attrs |= MountAttrFlags::MOUNT_ATTR_RDONLY;
+    }
+    if policy.nosuid
+        && !policy
+            .setuid_targets
+            .iter()
+            .any(|p| target.starts_with(p) || Path::new(p).starts_with(target))
+    {
+        attrs |= MountAttrFlags::MOUNT_ATTR_NOSUID;
+    }
+    if policy.nodev {
+        attrs |= MountAttrFlags::MOUNT_ATTR_NODEV;
+    }
+    if policy.noexec
+        && target.components().any(|c| {
+            policy
+                .noexec_dirs
+                .iter()
+                .any(|d| c.as_os_str() == d.as_str())
+        })
+    {
+        attrs |= MountAttrFlags::MOUNT_ATTR_NOEXEC;
+    }
+    if policy.nosymfollow {
+        attrs |= MountAttrFlags::MOUNT_ATTR_NOSYMFOLLOW;
+    }
+
+    attrs
+}
+
+fn mount_setattr(target: &Path, attrs: MountAttrFlags, recursive: bool) -> io::Result<()> {
+    let path = CString::new(target.as_os_str().as_bytes())?;
+    let attr = MountAttr {
+        attr_set: attrs.bits() as u64,
+        attr_clr: 0,
+        propagation: 0,
+        userns_fd: 0,
+    };
+
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_mount_setattr,
+            libc::AT_FDCWD,
+            path.as_ptr(),
+            if recursive { libc::AT_RECURSIVE } else { 0 },
+            &attr as *const MountAttr,
+            std::mem::size_of::<MountAttr>(),
+        )
+    };
+
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+// a bind remount replaces every per-mount flag, so the ones already set have to be carried over
+fn current_flags(target: &Path) -> MountFlags {
+    let Ok(mounts) = Process::myself().and_then(|p| p.mountinfo()) else {
+        return MountFlags::empty();
+    };
+    let Some(mount) = mounts.into_iter().rev().find(|m| m.mount_point == target) else {
+        return MountFlags::empty();
+    };
+
+    [
+        ("ro", MountFlags::RDONLY),
+        ("nosuid", MountFlags::NOSUID),
+        ("nodev", MountFlags::NODEV),
+        ("noexec", MountFlags::NOEXEC),
+        ("nosymfollow", MountFlags::NOSYMFOLLOW),
+        ("noatime", MountFlags::NOATIME),
+        ("nodiratime", MountFlags::NODIRATIME),
+        ("relatime", MountFlags::RELATIME),
+    ]
+    .into_iter()
+    .filter(|(name, _)| mount.mount_options.contains_key(*name))
+    .fold(MountFlags::empty(), |flags, (_, flag)| flags | flag)
+}
+
+fn remount(target: &Path, attrs: MountAttrFlags) -> rustix::io::Result<()> {
+    let flags = ATTRIBUTE_NAMES
+        .iter()
+        .filter(|(attr, _, _)| attrs.contains(*attr))
+        .fold(
+            MountFlags::BIND | current_flags(target),
+            |flags, (_, flag, _)| flags | *flag,
+        );
+
+    mount_remount(target, flags, "")
+}
+
+pub fn apply<P>(target: P, writable: bool)
+where
+    P: AsRef<Path>,
+{
+    harden(target.as_ref(), writable, false);
+}
+
+pub fn apply_tree<P>(target: P)
+where
+    P: AsRef<Path>,
+{
+    harden(target.as_ref(), false, true);
+}
+
+fn harden(target: &Path, writable: bool, recursive: bool) {
+    let Ok(policy) = POLICY.lock().map(|p| p.clone()) else {
+        return;
+    };
+    if !policy.enabled {
+        return;
+    }
+
+    let attrs = attributes_for(&policy, target, writable);
+    if attrs.is_empty() {
+        return;
+    }
+
+    let attributes = ATTRIBUTE_NAMES
+        .iter()
+        .filter(|(attr, _, _)| attrs.contains(*attr))
+        .map(|(_, _, name)| name.to_string())
+        .collect();
+
+    let (method, error) = match mount_setattr(target, attrs, recursive) {
+        Ok(()) => ("mount_setattr", None),
+        Err(e) => {
+            log::debug!(
+                "mount_setattr on {} failed: {}, fallback to remount",
+                target.display(),
+                e
+            );
+            match remount(target, attrs) {
+                Ok(()) => ("remount", None),
+                Err(e) => {
+                    log::warn!("Failed to harden mount {}: {}", target.display(), e);
+                    ("none", Some(e.to_string()))
+                }
+            }
+        }
+    };
+
+    if let Ok(mut records) = RECORDS.lock() {
+        records.push(HardenedMount {
+            target: target.display().to_string(),
+            attributes,
+            method: method.to_string(),
+            error,
+        });
+    }
+}
+
+pub fn take_records() -> Vec<HardenedMount> {
+    RECORDS
+        .lock()
+        .map(|mut records| std::mem::take(&mut *records))
+        .unwrap_or_default()
+}
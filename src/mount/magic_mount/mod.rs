@@ -1,12 +1,13 @@
 // Copyright 2026 https://github.com/Tools-cx-app/meta-magic_mount
 
+pub mod tree;
 mod utils;
 
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     fs,
     path::{Path, PathBuf},
-    sync::atomic::AtomicU32,
+    sync::Arc,
 };
 
 use anyhow::{Context, Result, bail};
@@ -14,25 +15,117 @@ use rustix::mount::{
     MountFlags, MountPropagationFlags, UnmountFlags, mount, mount_bind, mount_change, mount_move,
     mount_remount, unmount,
 };
+use serde::{Deserialize, Serialize};
 
 #[cfg(any(target_os = "linux", target_os = "android"))]
 use crate::mount::umount_mgr::send_umountable;
 use crate::{
     mount::{
-        magic_mount::utils::{clone_symlink, collect_module_files, mount_mirror},
+        hardening,
+        magic_mount::{
+            tree::{TreeEntry, TreeMarks},
+            utils::{Manifests, clone_symlink, collect_module_files, mount_mirror},
+        },
         node::{Node, NodeFileType},
     },
-    sys::fs::ensure_dir_exists,
+    sys::{fs::ensure_dir_exists, partitions},
+    utils::split_module_path,
 };
 
-static MOUNTED_FILES: AtomicU32 = AtomicU32::new(0);
-static MOUNTED_SYMBOLS_FILES: AtomicU32 = AtomicU32::new(0);
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MagicMountFailure {
+    pub path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub module: Option<String>,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MagicMountPoint {
+    pub path: PathBuf,
+    pub modules: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MagicMountReport {
+    pub mount_points: Vec<MagicMountPoint>,
+    pub bound_files: Vec<PathBuf>,
+    pub cloned_symlinks: Vec<PathBuf>,
+    pub tmpfs_skeletons: Vec<PathBuf>,
+    pub mirrored: Vec<PathBuf>,
+    pub mirror_binds: usize,
+    pub mirror_binds_saved: usize,
+    pub whiteouts: Vec<PathBuf>,
+    pub skipped: Vec<PathBuf>,
+    pub failures: Vec<MagicMountFailure>,
+}
+
+impl MagicMountReport {
+    fn merge(&mut self, other: Self) {
+        self.mount_points.extend(other.mount_points);
+        self.bound_files.extend(other.bound_files);
+        self.cloned_symlinks.extend(other.cloned_symlinks);
+        self.tmpfs_skeletons.extend(other.tmpfs_skeletons);
+        self.mirrored.extend(other.mirrored);
+        self.mirror_binds += other.mirror_binds;
+        self.mirror_binds_saved += other.mirror_binds_saved;
+        self.whiteouts.extend(other.whiteouts);
+        self.skipped.extend(other.skipped);
+        self.failures.extend(other.failures);
+    }
+
+    fn fail(&mut self, path: PathBuf, node: Option<&Node>, error: &anyhow::Error) {
+        let module = node
+            .and_then(|n| n.module_path.as_deref())
+            .and_then(split_module_path)
+            .map(|(id, _)| id);
+
+        self.failures.push(MagicMountFailure {
+            path,
+            module,
+            error: format!("{:#}", error),
+        });
+    }
+}
+
+fn owners(node: &Node) -> Vec<String> {
+    let mut owners: Vec<String> = node
+        .contributors
+        .iter()
+        .filter_map(|(path, _)| module_root(path))
+        .map(|(id, _)| id)
+        .collect();
+    owners.sort();
+    owners.dedup();
+    owners
+}
+
+fn culprit(node: &Node) -> Option<(String, PathBuf)> {
+    match node.contributors.as_slice() {
+        [(module_path, _)] => module_root(module_path),
+        _ => None,
+    }
+}
+
+fn module_root(module_path: &Path) -> Option<(String, PathBuf)> {
+    let (id, relative) = split_module_path(module_path)?;
+    let root = module_path
+        .ancestors()
+        .nth(relative.components().count())?
+        .to_path_buf();
+
+    Some((id, root))
+}
 
 struct MagicMount {
     node: Node,
     path: PathBuf,
     work_dir_path: PathBuf,
     has_tmpfs: bool,
+    report: MagicMountReport,
+    culprit: Option<(String, PathBuf)>,
+    manifests: Arc<Manifests>,
     #[cfg(any(target_os = "linux", target_os = "android"))]
     umount: bool,
 }
@@ -43,6 +136,7 @@ impl MagicMount {
         path: P,
         work_dir_path: P,
         has_tmpfs: bool,
+        manifests: &Arc<Manifests>,
         #[cfg(any(target_os = "linux", target_os = "android"))] umount: bool,
     ) -> Self
     where
@@ -53,11 +147,72 @@ impl MagicMount {
             path: path.as_ref().join(node.name.clone()),
             work_dir_path: work_dir_path.as_ref().join(node.name.clone()),
             has_tmpfs,
+            report: MagicMountReport::default(),
+            culprit: None,
+            manifests: Arc::clone(manifests),
             #[cfg(any(target_os = "linux", target_os = "android"))]
             umount,
         }
     }
 
+    fn mount_child(&mut self, node: &Node, has_tmpfs: bool) -> Result<()> {
+        let mut node = node.clone();
+
+        loop {
+            let mut child = Self::new(
+                &node,
+                &self.path,
+                &self.work_dir_path,
+                has_tmpfs,
+                &self.manifests,
+                #[cfg(any(target_os = "linux", target_os = "android"))]
+                self.umount,
+            );
+
+            let e = match child.do_mount() {
+                Ok(()) => {
+                    self.report.merge(child.report);
+                    return Ok(());
+                }
+                Err(e) => e,
+            };
+            let culprit = child.culprit.or_else(|| culprit(&child.node));
+
+            if has_tmpfs {
+                self.report.failures.extend(child.report.failures);
+                self.culprit = culprit;
+                return Err(e);
+            }
+
+            let Some((id, root)) = culprit else {
+                return Err(e);
+            };
+            if !node.prune_module(&root) {
+                return Err(e);
+            }
+
+            log::warn!(
+                "magic mount {} failed because of module {id}, retrying without it: {e:#}",
+                child.path.display()
+            );
+            self.report.failures.push(MagicMountFailure {
+                path: child.path.clone(),
+                module: Some(id),
+                error: format!("{e:#}"),
+            });
+
+            let _ = unmount(&child.work_dir_path, UnmountFlags::DETACH);
+            let _ = fs::remove_dir_all(&child.work_dir_path);
+
+            if node.children.is_empty()
+                && (node.contributors.is_empty()
+                    || (node.file_type == NodeFileType::Directory && !node.replace))
+            {
+                return Ok(());
+            }
+        }
+    }
+
     fn do_mount(&mut self) -> Result<()> {
         match self.node.file_type {
             NodeFileType::Symlink => self.symlink(),
@@ -65,6 +220,7 @@ impl MagicMount {
             NodeFileType::Directory => self.directory(),
             NodeFileType::Whiteout => {
                 log::debug!("file {} is removed", self.path.display());
+                self.report.whiteouts.push(self.path.clone());
                 Ok(())
             }
         }
@@ -72,7 +228,7 @@ impl MagicMount {
 }
 
 impl MagicMount {
-    fn symlink(&self) -> Result<()> {
+    fn symlink(&mut self) -> Result<()> {
         if let Some(module_path) = &self.node.module_path {
             log::debug!(
                 "create module symlink {} -> {}",
@@ -86,15 +242,14 @@ impl MagicMount {
                     self.work_dir_path.display(),
                 )
             })?;
-            let mounted = MOUNTED_SYMBOLS_FILES.load(std::sync::atomic::Ordering::Relaxed) + 1;
-            MOUNTED_SYMBOLS_FILES.store(mounted, std::sync::atomic::Ordering::Relaxed);
+            self.report.cloned_symlinks.push(self.path.clone());
             Ok(())
         } else {
             bail!("cannot mount root symlink {}!", self.path.display());
         }
     }
 
-    fn regular_file(&self) -> Result<()> {
+    fn regular_file(&mut self) -> Result<()> {
         let target = if self.has_tmpfs {
             fs::File::create(&self.work_dir_path)?;
             &self.work_dir_path
@@ -129,9 +284,15 @@ impl MagicMount {
         if let Err(e) = mount_remount(target, MountFlags::RDONLY | MountFlags::BIND, "") {
             log::warn!("make file {} ro: {e:#?}", target.display());
         }
+        if !self.has_tmpfs {
+            hardening::apply(target, false);
+            self.report.mount_points.push(MagicMountPoint {
+                path: self.path.clone(),
+                modules: owners(&self.node),
+            });
+        }
 
-        let mounted = MOUNTED_FILES.load(std::sync::atomic::Ordering::Relaxed) + 1;
-        MOUNTED_FILES.store(mounted, std::sync::atomic::Ordering::Relaxed);
+        self.report.bound_files.push(self.path.clone());
         Ok(())
     }
 
@@ -162,6 +323,7 @@ impl MagicMount {
                             self.path.display()
                         );
                         node.skip = true;
+                        self.report.skipped.push(real_path);
                         continue;
                     }
                     tmpfs = true;
@@ -170,9 +332,15 @@ impl MagicMount {
             }
         }
         let has_tmpfs = tmpfs || self.has_tmpfs;
+        let modules = if tmpfs {
+            owners(&self.node)
+        } else {
+            Vec::new()
+        };
 
         if has_tmpfs {
-            utils::tmpfs_skeleton(&self.path, &self.work_dir_path, &self.node)?;
+            utils::tmpfs_skeleton(&self.path, &self.work_dir_path, &self.node, &self.manifests)?;
+            self.report.tmpfs_skeletons.push(self.path.clone());
         }
 
         if tmpfs {
@@ -200,29 +368,22 @@ impl MagicMount {
             log::debug!("dir {} is replaced", self.path.display());
         }
 
-        for (name, node) in &self.node.children {
+        let children = std::mem::take(&mut self.node.children);
+        for (name, node) in &children {
             if node.skip {
                 continue;
             }
 
-            if let Err(e) = {
-                Self::new(
-                    node,
-                    &self.path,
-                    &self.work_dir_path,
-                    has_tmpfs,
-                    #[cfg(any(target_os = "linux", target_os = "android"))]
-                    self.umount,
-                )
-                .do_mount()
-            }
-            .with_context(|| format!("magic mount {}/{name}", self.path.display()))
+            if let Err(e) = self
+                .mount_child(node, has_tmpfs)
+                .with_context(|| format!("magic mount {}/{name}", self.path.display()))
             {
                 if has_tmpfs {
                     return Err(e);
                 }
 
                 log::error!("mount child {}/{name} failed: {e:#?}", self.path.display());
+                self.report.fail(self.path.join(name), Some(node), &e);
             }
         }
 
@@ -250,6 +411,11 @@ impl MagicMount {
             if let Err(e) = mount_change(&self.path, MountPropagationFlags::PRIVATE) {
                 log::warn!("make dir {} private: {e:#?}", self.path.display());
             }
+            hardening::apply_tree(&self.path);
+            self.report.mount_points.push(MagicMountPoint {
+                path: self.path.clone(),
+                modules,
+            });
 
             #[cfg(any(target_os = "linux", target_os = "android"))]
             if self.umount {
@@ -264,28 +430,22 @@ impl MagicMount {
     fn mount_path(&mut self, has_tmpfs: bool) -> Result<()> {
         for entry in self.path.read_dir()?.flatten() {
             let name = entry.file_name().to_string_lossy().to_string();
-            let result = {
-                if let Some(node) = self.node.children.remove(&name) {
-                    if node.skip {
-                        continue;
-                    }
-
-                    Self::new(
-                        &node,
-                        &self.path,
-                        &self.work_dir_path,
-                        has_tmpfs,
-                        #[cfg(any(target_os = "linux", target_os = "android"))]
-                        self.umount,
-                    )
-                    .do_mount()
-                    .with_context(|| format!("magic mount {}/{name}", self.path.display()))
-                } else if has_tmpfs {
-                    mount_mirror(&self.path, &self.work_dir_path, &entry)
-                        .with_context(|| format!("mount mirror {}/{name}", self.path.display()))
-                } else {
-                    Ok(())
+            let (result, node) = if let Some(node) = self.node.children.remove(&name) {
+                if node.skip {
+                    continue;
                 }
+
+                let result = self
+                    .mount_child(&node, has_tmpfs)
+                    .with_context(|| format!("magic mount {}/{name}", self.path.display()));
+                (result, Some(node))
+            } else if has_tmpfs {
+                let result =
+                    mount_mirror(&self.path, &self.work_dir_path, &entry, &mut self.report)
+                        .with_context(|| format!("mount mirror {}/{name}", self.path.display()));
+                (result, None)
+            } else {
+                continue;
             };
 
             if let Err(e) = result {
@@ -293,6 +453,7 @@ impl MagicMount {
                     return Err(e);
                 }
                 log::error!("mount child {}/{name} failed: {e:#?}", self.path.display());
+                self.report.fail(self.path.join(&name), node.as_ref(), &e);
             }
         }
 
@@ -306,13 +467,20 @@ pub fn magic_mount<P>(
     mount_source: &str,
     extra_partitions: &[String],
     need_id: HashSet<String>,
+    scopes: &HashMap<String, Vec<PathBuf>>,
     #[cfg(any(target_os = "linux", target_os = "android"))] umount: bool,
     #[cfg(not(any(target_os = "linux", target_os = "android")))] _umount: bool,
-) -> Result<()>
+) -> Result<MagicMountReport>
 where
     P: AsRef<Path>,
 {
-    if let Some(root) = collect_module_files(module_dir, extra_partitions, need_id)? {
+    if let Some((root, manifests)) = collect_module_files(
+        &partitions::resolve(extra_partitions),
+        module_dir,
+        extra_partitions,
+        need_id,
+        scopes,
+    )? {
         log::debug!("collected: {root:?}");
         let tmp_root = tmp_path.as_ref();
         let tmp_dir = tmp_root.join("workdir");
@@ -321,15 +489,28 @@ where
         mount(mount_source, &tmp_dir, "tmpfs", MountFlags::empty(), None).context("mount tmp")?;
         mount_change(&tmp_dir, MountPropagationFlags::PRIVATE).context("make tmp private")?;
 
-        let ret = MagicMount::new(
+        let mut magic = MagicMount::new(
             &root,
             Path::new("/"),
             tmp_dir.as_path(),
             false,
+            &Arc::new(manifests),
             #[cfg(any(target_os = "linux", target_os = "android"))]
             umount,
-        )
-        .do_mount();
+        );
+        let ret = magic.do_mount();
+
+        let tree = TreeEntry::build(
+            &root,
+            Path::new("/"),
+            &TreeMarks {
+                module_dir,
+                report: &magic.report,
+            },
+        );
+        if let Err(e) = tree::dump(&tree) {
+            log::warn!("{:#}", e);
+        }
 
         if let Err(e) = unmount(&tmp_dir, UnmountFlags::DETACH) {
             log::error!("failed to unmount tmp {e}");
@@ -337,12 +518,125 @@ where
 
         fs::remove_dir(tmp_dir).ok();
 
-        let mounted_symbols = MOUNTED_SYMBOLS_FILES.load(std::sync::atomic::Ordering::Relaxed);
-        let mounted_files = MOUNTED_FILES.load(std::sync::atomic::Ordering::Relaxed);
-        log::info!("mounted files: {mounted_files}, mounted symlinks: {mounted_symbols}");
-        ret
+        let report = magic.report;
+        log::info!(
+            "mounted files: {}, mounted symlinks: {}, tmpfs skeletons: {}, failures: {}",
+            report.bound_files.len(),
+            report.cloned_symlinks.len(),
+            report.tmpfs_skeletons.len(),
+            report.failures.len()
+        );
+        log::info!(
+            "mirrored {} stock entries with {} binds (at least {} binds avoided by whole-dir mirroring)",
+            report.mirrored.len(),
+            report.mirror_binds,
+            report.mirror_binds_saved
+        );
+        ret.map(|()| report)
     } else {
+        tree::clear();
         log::info!("no modules to mount, skipping!");
-        Ok(())
+        Ok(MagicMountReport::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, os::unix::fs::symlink};
+
+    use super::*;
+    use crate::{
+        conf::config::{Config, ModuleRules},
+        core::{inventory::Module, ops::planner},
+        sys::partitions::Partition,
+    };
+
+    fn touch(path: &Path) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "").unwrap();
+    }
+
+    fn owning_root(registry: &[Partition], path: &Path) -> PathBuf {
+        registry
+            .iter()
+            .map(Partition::mount_root)
+            .filter(|root| path.starts_with(root))
+            .max_by_key(|root| root.components().count())
+            .unwrap()
+    }
+
+    #[test]
+    fn planner_and_magic_mount_agree_on_partition_roots() {
+        let base = tempfile::tempdir().unwrap();
+        let base = base.path();
+        let sysroot = base.join("root");
+        let storage = base.join("storage");
+
+        for dir in [
+            "system/bin",
+            "system/product/etc",
+            "vendor/etc",
+            "my_product/etc",
+        ] {
+            fs::create_dir_all(sysroot.join(dir)).unwrap();
+        }
+        symlink("../vendor", sysroot.join("system/vendor")).unwrap();
+        symlink("../my_product", sysroot.join("system/my_product")).unwrap();
+        let sysroot = sysroot.canonicalize().unwrap();
+
+        let module = storage.join("parity");
+        fs::create_dir_all(&module).unwrap();
+        fs::write(module.join("module.prop"), "id=parity\n").unwrap();
+        for file in [
+            "system/bin/tool",
+            "system/product/etc/a.xml",
+            "system/my_product/etc/b.xml",
+            "vendor/etc/c.conf",
+            "my_product/etc/d.xml",
+        ] {
+            touch(&module.join(file));
+        }
+
+        let modules = [Module {
+            id: "parity".to_string(),
+            source_path: module.clone(),
+            rules: ModuleRules::default(),
+        }];
+        let registry = partitions::resolve_with(&sysroot, &[], &[]);
+
+        let plan =
+            planner::generate_with(&registry, &sysroot, &Config::default(), &modules, &storage)
+                .unwrap();
+        let planned: BTreeSet<PathBuf> = plan
+            .overlay_ops
+            .iter()
+            .map(|op| owning_root(&registry, Path::new(&op.target)))
+            .collect();
+
+        let need_id = HashSet::from(["parity".to_string()]);
+        let (root, _) = collect_module_files(&registry, &storage, &[], need_id, &HashMap::new())
+            .unwrap()
+            .unwrap();
+        let mut collected = BTreeSet::new();
+        for (name, node) in &root.children {
+            if name != "system" {
+                collected.insert(sysroot.join(name));
+                continue;
+            }
+            for child in node.children.keys() {
+                if registry.iter().any(|p| !p.is_system() && &p.name == child) {
+                    collected.insert(sysroot.join("system").join(child));
+                } else {
+                    collected.insert(sysroot.join("system"));
+                }
+            }
+        }
+
+        let expected: BTreeSet<PathBuf> = ["system", "system/product", "vendor", "my_product"]
+            .iter()
+            .map(|p| sysroot.join(p))
+            .collect();
+        assert_eq!(planned, expected);
+        assert_eq!(collected, expected);
     }
 }
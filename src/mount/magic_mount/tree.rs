@@ -0,0 +1,129 @@
+use std::{fmt, fs, path::Path};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    defs,
+    mount::{
+        magic_mount::MagicMountReport,
+        node::{Node, NodeFileType},
+    },
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TreeEntry {
+    pub path: String,
+    pub file_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub module: Option<String>,
+    pub replace: bool,
+    pub skip: bool,
+    pub tmpfs: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<TreeEntry>,
+}
+
+pub struct TreeMarks<'a> {
+    pub module_dir: &'a Path,
+    pub report: &'a MagicMountReport,
+}
+
+fn type_name(file_type: &NodeFileType) -> &'static str {
+    match file_type {
+        NodeFileType::RegularFile => "file",
+        NodeFileType::Directory => "directory",
+        NodeFileType::Symlink => "symlink",
+        NodeFileType::Whiteout => "whiteout",
+    }
+}
+
+fn owner(module_dir: &Path, node: &Node) -> Option<String> {
+    node.module_path
+        .as_ref()?
+        .strip_prefix(module_dir)
+        .ok()?
+        .iter()
+        .next()
+        .map(|id| id.to_string_lossy().to_string())
+}
+
+impl TreeEntry {
+    pub fn build(node: &Node, path: &Path, marks: &TreeMarks) -> Self {
+        let mut names: Vec<&String> = node.children.keys().collect();
+        names.sort();
+
+        Self {
+            path: path.display().to_string(),
+            file_type: type_name(&node.file_type).to_string(),
+            module: owner(marks.module_dir, node),
+            replace: node.replace,
+            skip: node.skip || marks.report.skipped.iter().any(|p| p == path),
+            tmpfs: marks.report.tmpfs_skeletons.iter().any(|p| p == path),
+            children: names
+                .into_iter()
+                .map(|name| Self::build(&node.children[name], &path.join(name), marks))
+                .collect(),
+        }
+    }
+
+    pub fn find(&self, prefix: &Path) -> Option<&Self> {
+        let path = Path::new(&self.path);
+        if path == prefix {
+            return Some(self);
+        }
+        if !prefix.starts_with(path) {
+            return None;
+        }
+        self.children.iter().find_map(|child| child.find(prefix))
+    }
+
+    fn render(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        let name = Path::new(&self.path)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.path.clone());
+
+        write!(f, "{}{} ({})", "  ".repeat(depth), name, self.file_type)?;
+        if let Some(module) = &self.module {
+            write!(f, " [{}]", module)?;
+        }
+        for (set, flag) in [
+            (self.replace, "replace"),
+            (self.skip, "skip"),
+            (self.tmpfs, "tmpfs"),
+        ] {
+            if set {
+                write!(f, " {}", flag)?;
+            }
+        }
+        writeln!(f)?;
+
+        for child in &self.children {
+            child.render(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TreeEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.render(f, 0)
+    }
+}
+
+pub fn dump(tree: &TreeEntry) -> Result<()> {
+    let json = serde_json::to_string(tree).context("Failed to serialize magic mount tree")?;
+    fs::write(defs::MAGIC_TREE_FILE, json)
+        .with_context(|| format!("Failed to write {}", defs::MAGIC_TREE_FILE))
+}
+
+pub fn clear() {
+    let _ = fs::remove_file(defs::MAGIC_TREE_FILE);
+}
+
+pub fn load() -> Result<TreeEntry> {
+    let content = fs::read_to_string(defs::MAGIC_TREE_FILE)
+        .with_context(|| format!("Failed to read {}", defs::MAGIC_TREE_FILE))?;
+    serde_json::from_str(&content).context("Failed to parse magic mount tree")
+}
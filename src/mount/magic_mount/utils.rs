@@ -1,5 +1,5 @@
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     fs::{self, DirEntry, Metadata, create_dir, create_dir_all, read_link},
     os::unix::fs::{MetadataExt, symlink},
     path::{Path, PathBuf},
@@ -8,16 +8,25 @@ use std::{
 use anyhow::{Result, bail};
 use rustix::{
     fs::{Gid, Mode, Uid, chmod, chown},
-    mount::mount_bind,
+    mount::{mount_bind, mount_bind_recursive},
 };
 
 use crate::{
+    core::inventory::{
+        attrs::{self, AttrManifest},
+        removals,
+    },
     defs::{DISABLE_FILE_NAME, REMOVE_FILE_NAME, SKIP_MOUNT_FILE_NAME},
-    mount::node::Node,
-    sys::fs::{lgetfilecon, lsetfilecon},
+    mount::{magic_mount::MagicMountReport, node::Node},
+    sys::{
+        fs::{lgetfilecon, lsetfilecon},
+        partitions::Partition,
+    },
     utils::validate_module_id,
 };
 
+pub type Manifests = HashMap<PathBuf, AttrManifest>;
+
 fn metadata_path<P>(path: P, node: &Node) -> Result<(Metadata, PathBuf)>
 where
     P: AsRef<Path>,
@@ -32,7 +41,12 @@ where
     }
 }
 
-pub fn tmpfs_skeleton<P>(path: P, work_dir_path: P, node: &Node) -> Result<()>
+pub fn tmpfs_skeleton<P>(
+    path: P,
+    work_dir_path: P,
+    node: &Node,
+    manifests: &Manifests,
+) -> Result<()>
 where
     P: AsRef<Path>,
 {
@@ -55,10 +69,24 @@ where
     )?;
     lsetfilecon(work_dir_path, lgetfilecon(path)?.as_str())?;
 
+    if let Some(module_path) = &node.module_path
+        && let Some((_, root)) = super::module_root(module_path)
+        && let Ok(relative) = module_path.strip_prefix(&root)
+        && let Some(manifest) = manifests.get(&root)
+        && let Some(attrs) = manifest.lookup(relative)
+    {
+        attrs::apply(work_dir_path, &attrs)?;
+    }
+
     Ok(())
 }
 
-pub fn mount_mirror<P>(path: P, work_dir_path: P, entry: &DirEntry) -> Result<()>
+pub fn mount_mirror<P>(
+    path: P,
+    work_dir_path: P,
+    entry: &DirEntry,
+    report: &mut MagicMountReport,
+) -> Result<()>
 where
     P: AsRef<Path>,
 {
@@ -74,6 +102,7 @@ where
         );
         fs::File::create(&work_dir_path)?;
         mount_bind(&path, &work_dir_path)?;
+        report.mirror_binds += 1;
     } else if file_type.is_dir() {
         log::debug!(
             "mount mirror dir {} -> {}",
@@ -81,17 +110,11 @@ where
             work_dir_path.display()
         );
         create_dir(&work_dir_path)?;
-        let metadata = entry.metadata()?;
-        chmod(&work_dir_path, Mode::from_raw_mode(metadata.mode()))?;
-        chown(
-            &work_dir_path,
-            Some(Uid::from_raw(metadata.uid())),
-            Some(Gid::from_raw(metadata.gid())),
-        )?;
-        lsetfilecon(&work_dir_path, lgetfilecon(&path)?.as_str())?;
-        for entry in path.read_dir()?.flatten() {
-            mount_mirror(&path, &work_dir_path, &entry)?;
-        }
+        mount_bind_recursive(&path, &work_dir_path)?;
+
+        let entries = fs::read_dir(&path).map(|dir| dir.count()).unwrap_or(0);
+        report.mirror_binds += 1;
+        report.mirror_binds_saved += entries.saturating_sub(1);
     } else if file_type.is_symlink() {
         log::debug!(
             "create mirror symlink {} -> {}",
@@ -100,19 +123,23 @@ where
         );
         clone_symlink(&path, &work_dir_path)?;
     }
+    report.mirrored.push(path);
 
     Ok(())
 }
 
 pub fn collect_module_files(
+    registry: &[Partition],
     module_dir: &Path,
     extra_partitions: &[String],
     need_id: HashSet<String>,
-) -> Result<Option<Node>> {
+    scopes: &HashMap<String, Vec<PathBuf>>,
+) -> Result<Option<(Node, Manifests)>> {
     let mut root = Node::new_root("");
     let mut system = Node::new_root("system");
     let module_root = module_dir;
     let mut has_file = HashSet::new();
+    let mut manifests = Manifests::new();
 
     log::debug!("begin collect module files: {}", module_root.display());
 
@@ -151,76 +178,85 @@ pub fn collect_module_files(
             continue;
         }
 
-        let mut modified = false;
-        let mut partitions = HashSet::new();
-        partitions.insert("system".to_string());
-        partitions.extend(extra_partitions.iter().cloned());
-
-        for p in &partitions {
-            if entry.path().join(p).is_dir() {
-                modified = true;
-                break;
-            }
-            log::debug!("{id} due not modify {p}");
-        }
+        let removed: Vec<PathBuf> = removals::collect(&entry.path(), extra_partitions)
+            .into_iter()
+            .filter(|rel| {
+                scopes
+                    .get(&id)
+                    .is_none_or(|scope| scope.iter().any(|s| rel.starts_with(s)))
+            })
+            .collect();
 
-        if !modified {
+        let has_partition =
+            !removed.is_empty() || registry.iter().any(|p| entry.path().join(&p.name).is_dir());
+        if !has_partition {
+            log::debug!("{id} does not modify any partition");
             continue;
         }
 
         log::debug!("collecting {}", entry.path().display());
 
-        for p in partitions {
-            if !entry.path().join(&p).exists() {
-                continue;
+        match AttrManifest::load(&entry.path()) {
+            Ok(Some(manifest)) => {
+                manifests.insert(entry.path(), manifest);
             }
-
-            has_file.insert(system.collect_module_files(entry.path().join(&p))?);
+            Ok(None) => {}
+            Err(e) => log::warn!("{:#}", e),
         }
-    }
 
-    if has_file.contains(&true) {
-        const BUILTIN_PARTITIONS: [(&str, bool); 4] = [
-            ("vendor", true),
-            ("system_ext", true),
-            ("product", true),
-            ("odm", false),
-        ];
-
-        for (partition, require_symlink) in BUILTIN_PARTITIONS {
-            let path_of_root = Path::new("/").join(partition);
-            let path_of_system = Path::new("/system").join(partition);
-            if path_of_root.is_dir() && (!require_symlink || path_of_system.is_symlink()) {
-                let name = partition.to_string();
-                if let Some(node) = system.children.remove(&name) {
-                    root.children.insert(name, node);
-                }
-            }
-        }
-
-        for partition in extra_partitions {
-            if BUILTIN_PARTITIONS.iter().any(|(p, _)| p == partition) {
+        for partition in registry {
+            if partition.name == "apex" {
                 continue;
             }
-            if partition == "system" {
+            let source = entry.path().join(&partition.name);
+            let partition_removed: Vec<&Path> = removed
+                .iter()
+                .filter_map(|rel| rel.strip_prefix(&partition.name).ok())
+                .collect();
+            if (!source.is_dir() && partition_removed.is_empty()) || !partition.exists() {
                 continue;
             }
 
-            let path_of_root = Path::new("/").join(partition);
-            let path_of_system = Path::new("/system").join(partition);
-            let require_symlink = false;
+            let created = !partition.is_system() && !system.children.contains_key(&partition.name);
+            let node = if partition.is_system() {
+                &mut system
+            } else {
+                system
+                    .children
+                    .entry(partition.name.clone())
+                    .or_insert_with(|| Node::new_partition(&partition.name, &source))
+            };
 
-            if path_of_root.is_dir() && (!require_symlink || path_of_system.is_symlink()) {
-                let name = partition.clone();
-                if let Some(node) = system.children.remove(&name) {
-                    log::debug!("attach extra partition '{name}' to root");
-                    root.children.insert(name, node);
+            let mut collected = match scopes.get(&id) {
+                _ if !source.is_dir() => false,
+                Some(scope) => {
+                    node.collect_module_files_scoped(&source, Path::new(&partition.name), scope)?
                 }
+                None => node.collect_module_files(&source)?,
+            };
+            for rel in partition_removed {
+                collected |= node.insert_whiteout(rel, &source);
+            }
+            if collected && !created && !partition.is_system() {
+                node.contributors.push((source.clone(), false));
+            }
+            if created && !collected {
+                system.children.remove(&partition.name);
+            }
+            has_file.insert(collected);
+        }
+    }
+
+    if has_file.contains(&true) {
+        for partition in registry.iter().filter(|p| p.is_standalone()) {
+            if let Some(node) = system.children.remove(&partition.name) {
+                log::debug!("attach partition '{}' to root", partition.name);
+                root.children.insert(partition.name.clone(), node);
             }
         }
 
         root.children.insert("system".to_string(), system);
-        Ok(Some(root))
+        Ok(Some((root, manifests)))
     } else {
         Ok(None)
     }
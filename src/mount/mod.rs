@@ -1,3 +1,4 @@
+pub mod hardening;
 pub mod magic_mount;
 pub mod node;
 pub mod overlayfs;
@@ -12,7 +12,10 @@ use std::{
 use anyhow::Result;
 use extattr::lgetxattr;
 
-use crate::defs::{REPLACE_DIR_FILE_NAME, REPLACE_DIR_XATTR};
+use crate::{
+    core::inventory::removals,
+    defs::{MAGIC_TREE_FILE, REPLACE_DIR_FILE_NAME, REPLACE_DIR_XATTR},
+};
 
 #[derive(PartialEq, Eq, Hash, Clone, Debug)]
 pub enum NodeFileType {
@@ -43,6 +46,7 @@ pub struct Node {
     pub children: HashMap<String, Self>,
     // the module that owned this node
     pub module_path: Option<PathBuf>,
+    pub contributors: Vec<(PathBuf, bool)>,
     pub replace: bool,
     pub skip: bool,
 }
@@ -51,7 +55,8 @@ impl fmt::Display for Node {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "u need to send '/data/adb/magic_mount/tree' to developer "
+            "magic mount tree of {} is dumped to '{}', send it to the developer",
+            self.name, MAGIC_TREE_FILE
         )
     }
 }
@@ -61,19 +66,77 @@ impl Node {
     where
         P: AsRef<Path>,
     {
-        let dir = module_dir.as_ref();
+        self.collect_files(module_dir.as_ref(), Path::new(""), None)
+    }
+
+    pub fn collect_module_files_scoped<P>(
+        &mut self,
+        module_dir: P,
+        relative: &Path,
+        scope: &[PathBuf],
+    ) -> Result<bool>
+    where
+        P: AsRef<Path>,
+    {
+        self.collect_files(module_dir.as_ref(), relative, Some(scope))
+    }
+
+    fn collect_files(
+        &mut self,
+        dir: &Path,
+        relative: &Path,
+        scope: Option<&[PathBuf]>,
+    ) -> Result<bool> {
         let mut has_file = false;
         for entry in dir.read_dir()?.flatten() {
             let name = entry.file_name().to_string_lossy().to_string();
+            let marker = entry
+                .metadata()
+                .ok()
+                .and_then(|meta| removals::marker_target(&name, &meta).map(str::to_string));
+            let name = marker.clone().unwrap_or(name);
+            let entry_relative = relative.join(&name);
+
+            let child_scope = match scope {
+                Some(scope) if scope.iter().any(|s| entry_relative.starts_with(s)) => None,
+                Some(scope)
+                    if entry.file_type().is_ok_and(|t| t.is_dir())
+                        && scope.iter().any(|s| s.starts_with(&entry_relative)) =>
+                {
+                    Some(scope)
+                }
+                Some(_) => continue,
+                None => None,
+            };
 
             let node = match self.children.entry(name.clone()) {
-                Entry::Occupied(o) => Some(o.into_mut()),
-                Entry::Vacant(v) => Self::new_module(&name, &entry).map(|it| v.insert(it)),
+                Entry::Occupied(o) => {
+                    let node = o.into_mut();
+                    if node.file_type == NodeFileType::Directory
+                        && marker.is_none()
+                        && entry.file_type().is_ok_and(|t| t.is_dir())
+                    {
+                        let replace = child_scope.is_none() && Self::dir_is_replace(entry.path());
+                        node.contributors.push((entry.path(), replace));
+                    }
+                    Some(node)
+                }
+                Entry::Vacant(v) if marker.is_some() => {
+                    Some(v.insert(Self::new_whiteout(&name, entry.path())))
+                }
+                Entry::Vacant(v) => Self::new_module(&name, &entry).map(|mut it| {
+                    if child_scope.is_some() {
+                        it.replace = false;
+                        it.contributors[0].1 = false;
+                    }
+                    v.insert(it)
+                }),
             };
 
             if let Some(node) = node {
                 has_file |= if node.file_type == NodeFileType::Directory {
-                    node.collect_module_files(dir.join(&node.name))? || node.replace
+                    node.collect_files(&dir.join(&node.name), &entry_relative, child_scope)?
+                        || node.replace
                 } else {
                     true
                 }
@@ -83,6 +146,56 @@ impl Node {
         Ok(has_file)
     }
 
+    pub fn insert_whiteout(&mut self, relative: &Path, module_path: &Path) -> bool {
+        let mut node = self;
+        let mut path = module_path.to_path_buf();
+        let mut components = relative.iter().peekable();
+
+        while let Some(component) = components.next() {
+            let name = component.to_string_lossy().to_string();
+            path.push(component);
+
+            if components.peek().is_none() {
+                if node.children.contains_key(&name) {
+                    return false;
+                }
+                node.children
+                    .insert(name.clone(), Self::new_whiteout(&name, path));
+                return true;
+            }
+
+            node = node
+                .children
+                .entry(name.clone())
+                .or_insert_with(|| Self::new_partition(&name, &path));
+            if node.file_type != NodeFileType::Directory {
+                return false;
+            }
+        }
+
+        false
+    }
+
+    pub fn prune_module(&mut self, module_root: &Path) -> bool {
+        let before = self.contributors.len();
+        self.contributors
+            .retain(|(p, _)| !p.starts_with(module_root));
+        let mut pruned = before != self.contributors.len();
+
+        if pruned {
+            self.module_path = self.contributors.first().map(|(p, _)| p.clone());
+            self.replace = self.contributors.first().is_some_and(|(_, r)| *r);
+        }
+
+        self.children.retain(|_, child| {
+            let owned = !child.contributors.is_empty();
+            pruned |= child.prune_module(module_root);
+            !(owned && child.contributors.is_empty() && child.children.is_empty())
+        });
+
+        pruned
+    }
+
     fn dir_is_replace<P>(path: P) -> bool
     where
         P: AsRef<Path>,
@@ -105,11 +218,29 @@ impl Node {
             file_type: NodeFileType::Directory,
             children: HashMap::default(),
             module_path: None,
+            contributors: Vec::new(),
             replace: false,
             skip: false,
         }
     }
 
+    pub fn new_partition(name: &str, source: &Path) -> Self {
+        Self {
+            module_path: Some(source.to_path_buf()),
+            contributors: vec![(source.to_path_buf(), false)],
+            ..Self::new_root(name)
+        }
+    }
+
+    pub fn new_whiteout(name: &str, module_path: PathBuf) -> Self {
+        Self {
+            file_type: NodeFileType::Whiteout,
+            contributors: vec![(module_path.clone(), false)],
+            module_path: Some(module_path),
+            ..Self::new_root(name)
+        }
+    }
+
     pub fn new_module<S>(name: &S, entry: &DirEntry) -> Option<Self>
     where
         S: ToString,
@@ -130,6 +261,7 @@ impl Node {
                     name: name.to_string(),
                     file_type,
                     children: HashMap::default(),
+                    contributors: vec![(path.clone(), replace)],
                     module_path: Some(path),
                     replace,
                     skip: false,
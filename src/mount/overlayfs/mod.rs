@@ -1,5 +1,6 @@
 // Copyright 2026 https://github.com/KernelSU-Modules-Repo/meta-overlayfs
 
+pub mod options;
 #[allow(clippy::module_inception)]
 pub mod overlayfs;
 pub mod utils;
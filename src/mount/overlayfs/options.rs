@@ -0,0 +1,225 @@
+use std::{
+    collections::HashMap,
+    ffi::CString,
+    fs,
+    os::fd::AsFd,
+    path::Path,
+    sync::{
+        LazyLock, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use anyhow::Result;
+use rustix::mount::{
+    FsOpenFlags, MountFlags, UnmountFlags, fsconfig_create, fsconfig_set_flag, fsconfig_set_string,
+    fsopen, mount, unmount,
+};
+
+use crate::{conf::config::OverlayOptions, sys::mount::ScratchMount};
+
+static PROBE_CACHE: LazyLock<Mutex<HashMap<String, Vec<MountParam>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+static PROBE_SEQ: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MountParam {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+impl MountParam {
+    fn flag(key: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            value: None,
+        }
+    }
+
+    fn value(key: &str, value: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            value: Some(value.to_string()),
+        }
+    }
+
+    fn on_off(key: &str, enabled: bool) -> Self {
+        Self::value(key, if enabled { "on" } else { "off" })
+    }
+
+    pub fn requires_upper(&self) -> bool {
+        self.key == "volatile"
+    }
+
+    pub fn to_data(&self) -> String {
+        match &self.value {
+            Some(value) => format!("{}={}", self.key, value.replace(',', "\\,")),
+            None => self.key.clone(),
+        }
+    }
+}
+
+pub fn to_params(options: &OverlayOptions) -> Vec<MountParam> {
+    let mut params = Vec::new();
+
+    if let Some(v) = &options.redirect_dir {
+        params.push(MountParam::value("redirect_dir", v));
+    }
+    if let Some(v) = options.metacopy {
+        params.push(MountParam::on_off("metacopy", v));
+    }
+    if let Some(v) = options.index {
+        params.push(MountParam::on_off("index", v));
+    }
+    if let Some(v) = &options.xino {
+        params.push(MountParam::value("xino", v));
+    }
+    if options.userxattr == Some(true) {
+        params.push(MountParam::flag("userxattr"));
+    }
+    if let Some(v) = options.override_creds {
+        params.push(MountParam::on_off("override_creds", v));
+    }
+    if options.volatile == Some(true) {
+        params.push(MountParam::flag("volatile"));
+    }
+    if let Some(v) = options.nfs_export {
+        params.push(MountParam::on_off("nfs_export", v));
+    }
+    if let Some(v) = &options.uuid {
+        params.push(MountParam::value("uuid", v));
+    }
+
+    params
+}
+
+fn probe_fsconfig(root: &Path, params: &[MountParam]) -> Result<()> {
+    let fs = fsopen("overlay", FsOpenFlags::FSOPEN_CLOEXEC)?;
+    let fs = fs.as_fd();
+    let lowerdir = format!(
+        "{}:{}",
+        root.join("lower1").display(),
+        root.join("lower2").display()
+    );
+
+    fsconfig_set_string(fs, "lowerdir", &lowerdir)?;
+    if params.iter().any(MountParam::requires_upper) {
+        fsconfig_set_string(fs, "upperdir", root.join("upper"))?;
+        fsconfig_set_string(fs, "workdir", root.join("work"))?;
+    }
+    for param in params {
+        match &param.value {
+            Some(value) => fsconfig_set_string(fs, param.key.as_str(), value.as_str())?,
+            None => fsconfig_set_flag(fs, param.key.as_str())?,
+        }
+    }
+    fsconfig_create(fs)?;
+
+    Ok(())
+}
+
+fn probe_legacy(root: &Path, params: &[MountParam]) -> Result<()> {
+    let merged = root.join("merged");
+    let mut data = format!(
+        "lowerdir={}:{}",
+        root.join("lower1").display(),
+        root.join("lower2").display()
+    );
+    if params.iter().any(MountParam::requires_upper) {
+        data = format!(
+            "{data},upperdir={},workdir={}",
+            root.join("upper").display(),
+            root.join("work").display()
+        );
+    }
+    for param in params {
+        data = format!("{data},{}", param.to_data());
+    }
+
+    mount(
+        "overlay",
+        &merged,
+        "overlay",
+        MountFlags::empty(),
+        Some(CString::new(data)?.as_c_str()),
+    )?;
+    let _ = unmount(&merged, UnmountFlags::DETACH);
+
+    Ok(())
+}
+
+fn set_key(params: &[MountParam]) -> String {
+    params
+        .iter()
+        .map(MountParam::to_data)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// probes the options together so that dependent ones (nfs_export needs index) are judged as a set
+fn probe(params: &[MountParam]) -> bool {
+    let name = format!(
+        "overlay_opt_probe_{}_{}",
+        std::process::id(),
+        PROBE_SEQ.fetch_add(1, Ordering::Relaxed)
+    );
+    let scratch = match ScratchMount::new(&name) {
+        Ok(scratch) => scratch,
+        Err(e) => {
+            log::warn!("Cannot probe overlay options {}: {:#}", set_key(params), e);
+            return false;
+        }
+    };
+    let root = scratch.path();
+
+    for dir in ["lower1", "lower2", "upper", "work", "merged"] {
+        if let Err(e) = fs::create_dir_all(root.join(dir)) {
+            log::warn!("Cannot prepare overlay option probe: {}", e);
+            return false;
+        }
+    }
+
+    match probe_fsconfig(root, params) {
+        Ok(()) => true,
+        Err(e) => {
+            log::debug!("fsconfig probe for {} failed: {:#}", set_key(params), e);
+            probe_legacy(root, params).is_ok()
+        }
+    }
+}
+
+pub fn validate(params: Vec<MountParam>) -> Vec<MountParam> {
+    if params.is_empty() {
+        return params;
+    }
+
+    let Ok(mut cache) = PROBE_CACHE.lock() else {
+        return Vec::new();
+    };
+
+    let key = set_key(&params);
+    if let Some(accepted) = cache.get(&key) {
+        return accepted.clone();
+    }
+
+    let accepted = if probe(&params) {
+        params
+    } else {
+        let mut accepted: Vec<MountParam> = Vec::new();
+        for param in params {
+            accepted.push(param);
+            if !probe(&accepted) {
+                let rejected = accepted.pop().map(|p| p.to_data()).unwrap_or_default();
+                log::warn!("overlay option '{}' rejected by kernel, ignoring", rejected);
+            }
+        }
+        accepted
+    };
+
+    cache.insert(key, accepted.clone());
+    accepted
+}
+
+pub fn resolve(options: &OverlayOptions) -> Vec<MountParam> {
+    validate(to_params(options))
+}
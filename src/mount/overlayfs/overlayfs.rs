@@ -2,42 +2,132 @@
 
 use std::{
     ffi::CString,
-    os::fd::AsFd,
+    os::fd::{AsFd, AsRawFd, OwnedFd},
     path::{Path, PathBuf},
-    time::{SystemTime, UNIX_EPOCH},
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
 use anyhow::{Context, Result, bail};
 use procfs::process::Process;
 use rustix::{
-    fs::CWD,
+    fs::{CWD, FileType, Mode, OFlags, ResolveFlags, fstat, openat, openat2},
+    io::Errno,
     mount::{MountFlags, MoveMountFlags, mount, move_mount},
 };
 
 use crate::{
     defs,
     mount::{
-        overlayfs::utils::{fs, umount_dir},
+        overlayfs::{
+            options::MountParam,
+            utils::{fs, umount_dir},
+        },
         umount_mgr::send_umountable,
     },
-    sys::fs::ensure_dir_exists,
+    sys::{capabilities, fs::ensure_dir_exists},
 };
 
 const MAX_LAYERS: usize = 64;
+const MAX_STACK: usize = 500;
+
+static STAGING_SEQ: AtomicUsize = AtomicUsize::new(0);
+
+fn open_path<Fd: AsFd>(dirfd: Fd, path: &Path, resolve: ResolveFlags) -> Result<OwnedFd> {
+    let flags = OFlags::PATH | OFlags::CLOEXEC;
+    match openat2(&dirfd, path, flags, Mode::empty(), resolve) {
+        Err(Errno::NOSYS) => Ok(openat(&dirfd, path, flags, Mode::empty())?),
+        result => Ok(result?),
+    }
+}
+
+fn fd_path(fd: &OwnedFd) -> String {
+    format!("/proc/self/fd/{}", fd.as_raw_fd())
+}
+
+fn next_staging_dir() -> Result<PathBuf> {
+    let staging_dir = Path::new(defs::RUN_DIR).join(format!(
+        "staging_{}_{}",
+        std::process::id(),
+        STAGING_SEQ.fetch_add(1, Ordering::Relaxed)
+    ));
+    ensure_dir_exists(&staging_dir)?;
+    Ok(staging_dir)
+}
+
+fn mount_legacy(
+    lower_dirs: &[String],
+    data_dirs: &[String],
+    upperdir: Option<&str>,
+    workdir: Option<&str>,
+    dest: &Path,
+    mount_source: &str,
+    params: &[MountParam],
+) -> Result<()> {
+    let mut layers: Vec<String> = lower_dirs.to_vec();
+    while layers.len() > MAX_LAYERS {
+        let split_idx = layers.len() - (MAX_LAYERS - 1);
+        let bottom_chunk: Vec<String> = layers.drain(split_idx..).collect();
+        let staging_dir = next_staging_dir()?;
+
+        mount_legacy(
+            &bottom_chunk,
+            &[],
+            None,
+            None,
+            &staging_dir,
+            mount_source,
+            params,
+        )?;
+        let _ = send_umountable(&staging_dir);
+
+        layers.push(staging_dir.to_string_lossy().to_string());
+    }
+
+    let mut lower = layers.join(":").replace(',', "\\,");
+    for dir in data_dirs {
+        lower = format!("{lower}::{}", dir.replace(',', "\\,"));
+    }
+    let mut data = format!("lowerdir={lower}");
+
+    let has_upper = upperdir.is_some() && workdir.is_some();
+    if let (Some(upperdir), Some(workdir)) = (upperdir, workdir) {
+        data = format!(
+            "{data},upperdir={},workdir={}",
+            upperdir.replace(',', "\\,"),
+            workdir.replace(',', "\\,")
+        );
+    }
+    for param in params.iter().filter(|p| has_upper || !p.requires_upper()) {
+        data = format!("{data},{}", param.to_data());
+    }
+    if !data_dirs.is_empty() && !params.iter().any(|p| p.key == "metacopy") {
+        data = format!("{data},metacopy=on");
+    }
+
+    mount(
+        mount_source,
+        dest,
+        "overlay",
+        MountFlags::empty(),
+        Some(CString::new(data)?.as_c_str()),
+    )?;
+    Ok(())
+}
 
 fn mount_overlay_core(
     lower_dirs: &[String],
+    data_dirs: &[String],
     upperdir: Option<&Path>,
     workdir: Option<&Path>,
     dest: &Path,
     mount_source: &str,
+    params: &[MountParam],
 ) -> Result<()> {
-    let lowerdir_config = lower_dirs.join(":");
-
     log::debug!(
-        "core mount overlayfs on {:?}, layers={}, source={}",
+        "core mount overlayfs on {:?}, layers={}, data layers={}, source={}",
         dest,
         lower_dirs.len(),
+        data_dirs.len(),
         mount_source
     );
 
@@ -48,59 +138,70 @@ fn mount_overlay_core(
         .filter(|wd| wd.exists())
         .map(|e| e.display().to_string());
 
+    let has_upper = upperdir_s.is_some() && workdir_s.is_some();
+    let params: Vec<MountParam> = params
+        .iter()
+        .filter(|p| has_upper || !p.requires_upper())
+        .cloned()
+        .collect();
+
     if let Err(e) = fs(
         upperdir_s.clone(),
         workdir_s.clone(),
-        lowerdir_config.clone(),
+        lower_dirs,
+        data_dirs,
+        &params,
         mount_source,
         dest,
     ) {
         log::warn!("fsopen mount failed: {:#}, fallback to mount", e);
-        let safe_lower = lowerdir_config.replace(',', "\\,");
-        let mut data = format!("lowerdir={safe_lower}");
-
-        if let (Some(upperdir), Some(workdir)) = (upperdir_s, workdir_s) {
-            data = format!(
-                "{data},upperdir={},workdir={}",
-                upperdir.replace(',', "\\,"),
-                workdir.replace(',', "\\,")
-            );
-        }
-        mount(
-            mount_source,
+        mount_legacy(
+            lower_dirs,
+            data_dirs,
+            upperdir_s.as_deref(),
+            workdir_s.as_deref(),
             dest,
-            "overlay",
-            MountFlags::empty(),
-            Some(CString::new(data)?.as_c_str()),
+            mount_source,
+            &params,
         )?;
     }
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn mount_overlayfs(
     lower_dirs: &[String],
     lowest: &str,
+    data_dirs: &[String],
     upperdir: Option<PathBuf>,
     workdir: Option<PathBuf>,
     dest: impl AsRef<Path>,
     mount_source: &str,
+    params: &[MountParam],
 ) -> Result<()> {
     let mut current_layers: Vec<String> = lower_dirs.to_vec();
     current_layers.push(lowest.to_string());
 
-    while current_layers.len() > MAX_LAYERS {
-        let split_idx = current_layers.len().saturating_sub(MAX_LAYERS - 1);
-        let bottom_chunk: Vec<String> = current_layers.drain(split_idx..).collect();
-
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_nanos();
-        let staging_dir = Path::new(defs::RUN_DIR).join(format!("staging_{}", timestamp));
+    let max_layers = if capabilities::probe().lowerdir_plus {
+        MAX_STACK
+    } else {
+        MAX_LAYERS
+    };
 
-        ensure_dir_exists(&staging_dir)?;
+    while current_layers.len() > max_layers {
+        let split_idx = current_layers.len().saturating_sub(max_layers - 1);
+        let bottom_chunk: Vec<String> = current_layers.drain(split_idx..).collect();
+        let staging_dir = next_staging_dir()?;
 
-        mount_overlay_core(&bottom_chunk, None, None, &staging_dir, mount_source)?;
+        mount_overlay_core(
+            &bottom_chunk,
+            &[],
+            None,
+            None,
+            &staging_dir,
+            mount_source,
+            params,
+        )?;
 
         let _ = send_umountable(&staging_dir);
 
@@ -109,10 +210,12 @@ pub fn mount_overlayfs(
 
     mount_overlay_core(
         &current_layers,
+        data_dirs,
         upperdir.as_deref(),
         workdir.as_deref(),
         dest.as_ref(),
         mount_source,
+        params,
     )
 }
 
@@ -156,17 +259,20 @@ fn mount_overlay_child(
     mount_point: &str,
     relative: &String,
     module_roots: &Vec<String>,
-    stock_root: &String,
+    stock: &OwnedFd,
     mount_source: &str,
-) -> Result<()> {
+    params: &[MountParam],
+) -> Result<bool> {
+    let stock_root = fd_path(stock);
     if !module_roots
         .iter()
         .any(|lower| Path::new(&format!("{lower}{relative}")).exists())
     {
-        return bind_mount(stock_root, mount_point);
+        bind_mount(&stock_root, mount_point)?;
+        return Ok(true);
     }
-    if !Path::new(&stock_root).is_dir() {
-        return Ok(());
+    if FileType::from_raw_mode(fstat(stock)?.st_mode) != FileType::Directory {
+        return Ok(false);
     }
     let mut lower_dirs: Vec<String> = vec![];
     for lower in module_roots {
@@ -175,25 +281,27 @@ fn mount_overlay_child(
         if path.is_dir() {
             lower_dirs.push(lower_dir);
         } else if path.exists() {
-            return Ok(());
+            return Ok(false);
         }
     }
     if lower_dirs.is_empty() {
-        return Ok(());
+        return Ok(false);
     }
     if let Err(e) = mount_overlayfs(
         &lower_dirs,
-        stock_root,
+        &stock_root,
+        &[],
         None,
         None,
         mount_point,
         mount_source,
+        params,
     ) {
         log::warn!("failed: {:#}, fallback to bind mount", e);
-        bind_mount(stock_root, mount_point)?;
+        bind_mount(&stock_root, mount_point)?;
     }
     let _ = send_umountable(mount_point);
-    Ok(())
+    Ok(true)
 }
 
 pub fn mount_overlay(
@@ -202,10 +310,11 @@ pub fn mount_overlay(
     workdir: Option<PathBuf>,
     upperdir: Option<PathBuf>,
     mount_source: &str,
-) -> Result<()> {
+    params: &[MountParam],
+) -> Result<Vec<String>> {
     log::info!("mount overlay for {}", root);
-    std::env::set_current_dir(root).with_context(|| format!("failed to chdir to {root}"))?;
-    let stock_root = ".";
+    let stock_root = open_path(CWD, Path::new(root), ResolveFlags::NO_MAGICLINKS)
+        .with_context(|| format!("failed to open stock root {root}"))?;
 
     let mounts = Process::myself()?
         .mountinfo()
@@ -221,32 +330,50 @@ pub fn mount_overlay(
     mount_seq.sort();
     mount_seq.dedup();
 
-    mount_overlayfs(module_roots, root, upperdir, workdir, root, mount_source)
-        .with_context(|| "mount overlayfs for root failed")?;
+    mount_overlayfs(
+        module_roots,
+        root,
+        &[],
+        upperdir,
+        workdir,
+        root,
+        mount_source,
+        params,
+    )
+    .with_context(|| "mount overlayfs for root failed")?;
+    let mut mounted = vec![root.clone()];
     for mount_point in mount_seq.iter() {
         let Some(mount_point) = mount_point else {
             continue;
         };
         let relative = mount_point.replacen(root, "", 1);
-        let stock_root: String = format!("{stock_root}{relative}");
-        if !Path::new(&stock_root).exists() {
+        let Ok(stock) = open_path(
+            &stock_root,
+            Path::new(relative.trim_start_matches('/')),
+            ResolveFlags::BENEATH | ResolveFlags::NO_MAGICLINKS,
+        ) else {
             continue;
-        }
-        if let Err(e) = mount_overlay_child(
+        };
+        match mount_overlay_child(
             mount_point,
             &relative,
             module_roots,
-            &stock_root,
+            &stock,
             mount_source,
+            params,
         ) {
-            log::warn!(
-                "failed to mount overlay for child {}: {:#}, revert",
-                mount_point,
-                e
-            );
-            umount_dir(root).with_context(|| format!("failed to revert {root}"))?;
-            bail!(e);
+            Ok(true) => mounted.push(mount_point.to_string()),
+            Ok(false) => {}
+            Err(e) => {
+                log::warn!(
+                    "failed to mount overlay for child {}: {:#}, revert",
+                    mount_point,
+                    e
+                );
+                umount_dir(root).with_context(|| format!("failed to revert {root}"))?;
+                bail!(e);
+            }
         }
     }
-    Ok(())
+    Ok(mounted)
 }
@@ -1,12 +1,11 @@
 // Copyright 2026 https://github.com/KernelSU-Modules-Repo/meta-overlayfs and https://github.com/bmax121/APatch
 
+use std::os::fd::AsFd;
 #[cfg(any(target_os = "linux", target_os = "android"))]
 use std::{fs, os::unix::fs::PermissionsExt, path::Path};
-use std::{io::Read, os::fd::AsFd};
 
 #[cfg(any(target_os = "linux", target_os = "android"))]
-use anyhow::{Context, Result};
-use flate2::read::GzDecoder;
+use anyhow::{Context, Result, bail};
 #[cfg(any(target_os = "linux", target_os = "android"))]
 use loopdev::LoopControl;
 #[cfg(any(target_os = "linux", target_os = "android"))]
@@ -15,10 +14,12 @@ use rustix::{
     fs::CWD,
     mount::{
         FsMountFlags, FsOpenFlags, MountAttrFlags, MoveMountFlags, fsconfig_create,
-        fsconfig_set_string, fsmount, fsopen, move_mount,
+        fsconfig_set_flag, fsconfig_set_string, fsmount, fsopen, move_mount,
     },
 };
 
+use crate::{mount::overlayfs::options::MountParam, sys::capabilities};
+
 #[cfg(any(target_os = "linux", target_os = "android"))]
 pub fn mount_ext4<P>(source: P, target: P) -> Result<()>
 where
@@ -75,27 +76,7 @@ where
 }
 
 pub fn is_overlay_supported() -> Result<bool> {
-    let file = fs::File::open("/proc/config.gz")?;
-
-    let mut config = String::new();
-    let mut decoder = GzDecoder::new(file);
-    decoder.read_to_string(&mut config)?;
-
-    for i in config.lines() {
-        if i.starts_with("#") {
-            continue;
-        }
-
-        let Some((k, v)) = i.split_once('=') else {
-            continue;
-        };
-
-        if k.trim() == "CONFIG_OVERLAY_FS" && v.trim() == "y" {
-            return Ok(true);
-        }
-    }
-
-    Ok(false)
+    Ok(crate::sys::capabilities::probe().overlay_supported())
 }
 
 #[cfg(any(target_os = "linux", target_os = "android"))]
@@ -108,7 +89,9 @@ pub fn umount_dir(src: impl AsRef<Path>) -> Result<()> {
 pub fn fs<S, P>(
     upperdir: Option<String>,
     workdir: Option<String>,
-    lowerdir_config: String,
+    lower_dirs: &[String],
+    data_dirs: &[String],
+    params: &[MountParam],
     source: S,
     dest: P,
 ) -> Result<()>
@@ -116,16 +99,44 @@ where
     S: ToString,
     P: AsRef<Path>,
 {
+    if !data_dirs.is_empty() && !capabilities::probe().datadir_plus {
+        bail!("data-only layers require datadir+ support");
+    }
+
     let fs = fsopen("overlay", FsOpenFlags::FSOPEN_CLOEXEC).context("Failed to fsopen overlay")?;
     let fs = fs.as_fd();
-    fsconfig_set_string(fs, "lowerdir", &lowerdir_config)
-        .context("Failed to fsconfig set string lowerdir with {lowerdir_config}")?;
+    if capabilities::probe().lowerdir_plus {
+        for dir in lower_dirs {
+            fsconfig_set_string(fs, "lowerdir+", dir)
+                .with_context(|| format!("Failed to fsconfig append lowerdir+ {dir}"))?;
+        }
+        for dir in data_dirs {
+            fsconfig_set_string(fs, "datadir+", dir)
+                .with_context(|| format!("Failed to fsconfig append datadir+ {dir}"))?;
+        }
+        if !data_dirs.is_empty() && !params.iter().any(|p| p.key == "metacopy") {
+            fsconfig_set_string(fs, "metacopy", "on")
+                .context("Failed to fsconfig set metacopy for data-only layers")?;
+        }
+    } else {
+        let lowerdir_config = lower_dirs.join(":");
+        fsconfig_set_string(fs, "lowerdir", &lowerdir_config).with_context(|| {
+            format!("Failed to fsconfig set string lowerdir with {lowerdir_config}")
+        })?;
+    }
     if let (Some(upperdir), Some(workdir)) = (&upperdir, &workdir) {
         fsconfig_set_string(fs, "upperdir", upperdir)
             .context("Failed to fsconfig set string upperdir with {upperdir}")?;
         fsconfig_set_string(fs, "workdir", workdir)
             .context("Failed to fsconfig set string workdir with {workdir}")?;
     }
+    for param in params {
+        match &param.value {
+            Some(value) => fsconfig_set_string(fs, param.key.as_str(), value.as_str()),
+            None => fsconfig_set_flag(fs, param.key.as_str()),
+        }
+        .with_context(|| format!("Failed to fsconfig set {}", param.to_data()))?;
+    }
     fsconfig_set_string(fs, "source", source.to_string())
         .context("Failed to fsconfig set string source with {source}")?;
     fsconfig_create(fs).context("Failed to fsconfig create new fs")?;
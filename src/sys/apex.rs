@@ -0,0 +1,115 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use regex_lite::Regex;
+
+use crate::defs;
+
+static ACTIVE: OnceLock<Option<Vec<ApexInfo>>> = OnceLock::new();
+
+#[derive(Debug, Clone)]
+pub struct ApexInfo {
+    pub name: String,
+    pub version: String,
+}
+
+impl ApexInfo {
+    // apexd mounts every active apex at both /apex/<name> and /apex/<name>@<version>
+    pub fn mountpoints(&self) -> Vec<PathBuf> {
+        [self.name.clone(), format!("{}@{}", self.name, self.version)]
+            .into_iter()
+            .map(|dir| Path::new("/apex").join(dir))
+            .filter(|p| p.is_dir())
+            .collect()
+    }
+}
+
+fn parse(xml: &str) -> Vec<ApexInfo> {
+    let element = Regex::new(r"<apex-info\b([^>]*)>").unwrap();
+    let attribute = Regex::new(r#"(\w+)="([^"]*)""#).unwrap();
+
+    element
+        .captures_iter(xml)
+        .filter_map(|element| {
+            let attrs: HashMap<&str, &str> = attribute
+                .captures_iter(element.get(1)?.as_str())
+                .filter_map(|a| Some((a.get(1)?.as_str(), a.get(2)?.as_str())))
+                .collect();
+
+            if attrs.get("isActive") != Some(&"true") {
+                return None;
+            }
+            Some(ApexInfo {
+                name: attrs.get("moduleName")?.to_string(),
+                version: attrs.get("versionCode")?.to_string(),
+            })
+        })
+        .collect()
+}
+
+fn load() -> Option<Vec<ApexInfo>> {
+    match fs::read_to_string(defs::APEX_INFO_LIST) {
+        Ok(xml) => {
+            let active = parse(&xml);
+            log::debug!("{} active apex modules", active.len());
+            Some(active)
+        }
+        Err(e) => {
+            log::debug!("apex info list unavailable: {}", e);
+            None
+        }
+    }
+}
+
+pub fn active() -> Option<&'static [ApexInfo]> {
+    ACTIVE.get_or_init(load).as_deref()
+}
+
+pub fn split_versioned(dir: &str) -> (&str, Option<&str>) {
+    match dir.split_once('@') {
+        Some((name, version)) => (name, Some(version)),
+        None => (dir, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_keeps_only_active_apexes() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<apex-info-list>
+    <apex-info moduleName="com.android.art" modulePath="/system/apex/com.android.art.apex" preinstalledModulePath="/system/apex/com.android.art.apex" versionCode="340090000" versionName="" isFactory="true" isActive="true" lastUpdateMillis="1230768000000" provideSharedApexLibs="false" />
+    <apex-info moduleName="com.android.art" modulePath="/data/apex/active/com.android.art@341000000.apex" versionCode="341000000" isFactory="false" isActive="false" />
+    <apex-info moduleName="com.android.conscrypt" versionCode="2" isActive="true"/>
+    <apex-info versionCode="3" isActive="true"/>
+</apex-info-list>"#;
+
+        let active = parse(xml);
+        let found: Vec<(&str, &str)> = active
+            .iter()
+            .map(|a| (a.name.as_str(), a.version.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("com.android.art", "340090000"),
+                ("com.android.conscrypt", "2")
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_ignores_unrelated_markup() {
+        assert!(parse("").is_empty());
+        assert!(parse("<apex-info-list></apex-info-list>").is_empty());
+        assert!(
+            parse(r#"<apex-infos moduleName="x" versionCode="1" isActive="true"/>"#).is_empty()
+        );
+    }
+}
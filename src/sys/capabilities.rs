@@ -0,0 +1,161 @@
+use std::{ffi::CString, fs, os::fd::AsFd, path::Path, sync::OnceLock};
+
+use anyhow::Result;
+use extattr::{Flags as XattrFlags, lgetxattr, lsetxattr};
+use rustix::mount::{
+    FsOpenFlags, MountFlags, UnmountFlags, fsconfig_create, fsconfig_set_string, fsopen, mount,
+    unmount,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    conf::config::OverlayMode,
+    sys::{fs::OVERLAY_OPAQUE_XATTR, mount::ScratchMount},
+};
+
+static CAPABILITIES: OnceLock<KernelCapabilities> = OnceLock::new();
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KernelCapabilities {
+    pub overlay_in_filesystems: bool,
+    pub overlay_mount: bool,
+    #[serde(rename = "tmpfs_xattr_supported")]
+    pub tmpfs_xattr: bool,
+    pub fsopen: bool,
+    pub lowerdir_plus: bool,
+    pub datadir_plus: bool,
+    pub erofs: bool,
+    pub supported_overlay_modes: Vec<OverlayMode>,
+}
+
+impl KernelCapabilities {
+    pub fn overlay_supported(&self) -> bool {
+        self.overlay_mount || self.overlay_in_filesystems
+    }
+}
+
+fn filesystems() -> Vec<String> {
+    fs::read_to_string("/proc/filesystems")
+        .map(|content| {
+            content
+                .lines()
+                .filter_map(|line| line.split_whitespace().last())
+                .map(|name| name.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn prepare(root: &Path) -> Result<()> {
+    for dir in ["lower1", "lower2", "merged", "xattr"] {
+        fs::create_dir_all(root.join(dir))?;
+    }
+    fs::write(root.join("lower1").join("probe"), "1")?;
+    Ok(())
+}
+
+fn probe_overlay_mount(root: &Path) -> Result<()> {
+    let merged = root.join("merged");
+    let data = format!(
+        "lowerdir={}:{}",
+        root.join("lower1").display(),
+        root.join("lower2").display()
+    );
+
+    mount(
+        "overlay",
+        &merged,
+        "overlay",
+        MountFlags::RDONLY,
+        Some(CString::new(data)?.as_c_str()),
+    )?;
+    let visible = merged.join("probe").exists();
+    let _ = unmount(&merged, UnmountFlags::DETACH);
+
+    if !visible {
+        anyhow::bail!("overlay mounted but lower content is not visible");
+    }
+    Ok(())
+}
+
+fn probe_tmpfs_xattr(root: &Path) -> Result<()> {
+    let target = root.join("xattr");
+    lsetxattr(&target, OVERLAY_OPAQUE_XATTR, b"y", XattrFlags::empty())?;
+    if lgetxattr(&target, OVERLAY_OPAQUE_XATTR)? != b"y" {
+        anyhow::bail!("trusted xattr read back mismatch");
+    }
+    Ok(())
+}
+
+fn probe_lowerdir_plus(root: &Path) -> Result<()> {
+    let fs = fsopen("overlay", FsOpenFlags::FSOPEN_CLOEXEC)?;
+    let fs = fs.as_fd();
+    fsconfig_set_string(fs, "lowerdir+", root.join("lower1"))?;
+    fsconfig_set_string(fs, "lowerdir+", root.join("lower2"))?;
+    fsconfig_create(fs)?;
+    Ok(())
+}
+
+fn probe_datadir_plus(root: &Path) -> Result<()> {
+    let fs = fsopen("overlay", FsOpenFlags::FSOPEN_CLOEXEC)?;
+    let fs = fs.as_fd();
+    fsconfig_set_string(fs, "lowerdir+", root.join("lower1"))?;
+    fsconfig_set_string(fs, "datadir+", root.join("lower2"))?;
+    fsconfig_set_string(fs, "metacopy", "on")?;
+    fsconfig_create(fs)?;
+    Ok(())
+}
+
+fn detect() -> KernelCapabilities {
+    let filesystems = filesystems();
+    let mut caps = KernelCapabilities {
+        overlay_in_filesystems: filesystems.iter().any(|fs| fs == "overlay"),
+        erofs: filesystems.iter().any(|fs| fs == "erofs"),
+        fsopen: fsopen("overlay", FsOpenFlags::FSOPEN_CLOEXEC).is_ok(),
+        ..Default::default()
+    };
+
+    match ScratchMount::new("capability_probe") {
+        Ok(scratch) => {
+            let root = scratch.path();
+            if let Err(e) = prepare(root) {
+                log::warn!("Cannot prepare capability probe: {:#}", e);
+            } else {
+                caps.overlay_mount = probe_overlay_mount(root)
+                    .inspect_err(|e| log::debug!("overlay mount probe failed: {:#}", e))
+                    .is_ok();
+                caps.tmpfs_xattr = probe_tmpfs_xattr(root)
+                    .inspect_err(|e| log::debug!("tmpfs xattr probe failed: {:#}", e))
+                    .is_ok();
+                caps.lowerdir_plus = caps.fsopen
+                    && probe_lowerdir_plus(root)
+                        .inspect_err(|e| log::debug!("lowerdir+ probe failed: {:#}", e))
+                        .is_ok();
+                caps.datadir_plus = caps.lowerdir_plus
+                    && probe_datadir_plus(root)
+                        .inspect_err(|e| log::debug!("datadir+ probe failed: {:#}", e))
+                        .is_ok();
+            }
+        }
+        Err(e) => log::warn!("Cannot run kernel capability probe: {:#}", e),
+    }
+
+    if caps.overlay_supported() {
+        if caps.tmpfs_xattr {
+            caps.supported_overlay_modes.push(OverlayMode::Tmpfs);
+        }
+        caps.supported_overlay_modes.push(OverlayMode::Ext4);
+        if caps.erofs {
+            caps.supported_overlay_modes.push(OverlayMode::Erofs);
+        }
+    }
+
+    log::debug!("kernel capabilities: {:?}", caps);
+
+    caps
+}
+
+pub fn probe() -> &'static KernelCapabilities {
+    CAPABILITIES.get_or_init(detect)
+}
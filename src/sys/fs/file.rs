@@ -78,6 +78,10 @@ fn make_device_node(path: &Path, mode: u32, rdev: u64) -> Result<()> {
     Ok(())
 }
 
+pub fn make_whiteout(path: &Path) -> Result<()> {
+    make_device_node(path, libc::S_IFCHR | 0o644, 0)
+}
+
 fn native_cp_r(
     src: &Path,
     dst: &Path,
@@ -1,13 +1,14 @@
-use std::path::Path;
 #[cfg(any(target_os = "linux", target_os = "android"))]
-use std::{fs, io::Read, os::unix::ffi::OsStrExt};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
 
 use anyhow::{Context, Result};
 #[cfg(any(target_os = "linux", target_os = "android"))]
 use extattr::{Flags as XattrFlags, lgetxattr, llistxattr, lsetxattr};
 
 const SELINUX_XATTR: &str = "security.selinux";
-const OVERLAY_OPAQUE_XATTR: &str = "trusted.overlay.opaque";
+const CAPABILITY_XATTR: &str = "security.capability";
+pub const OVERLAY_OPAQUE_XATTR: &str = "trusted.overlay.opaque";
 
 #[cfg(any(target_os = "linux", target_os = "android"))]
 fn copy_extended_attributes(src: &Path, dst: &Path) -> Result<()> {
@@ -91,30 +92,36 @@ pub fn lgetfilecon<P: AsRef<Path>>(_path: P) -> Result<String> {
     unimplemented!();
 }
 
+// stored as vfs_cap_data revision 2 with every permitted bit also effective
 #[cfg(any(target_os = "linux", target_os = "android"))]
-pub fn is_overlay_xattr_supported() -> Result<bool> {
-    use flate2::read::GzDecoder;
-    let file = fs::File::open("/proc/config.gz")?;
-
-    let mut config = String::new();
-    let mut decoder = GzDecoder::new(file);
-    decoder.read_to_string(&mut config)?;
-
-    for i in config.lines() {
-        if i.starts_with("#") {
-            continue;
-        }
-
-        let Some((k, v)) = i.split_once('=') else {
-            continue;
-        };
+pub fn set_file_capabilities<P: AsRef<Path>>(path: P, caps: u64) -> Result<()> {
+    const VFS_CAP_REVISION_2: u32 = 0x0200_0000;
+    const VFS_CAP_FLAGS_EFFECTIVE: u32 = 0x1;
+
+    let mut data = Vec::with_capacity(20);
+    data.extend((VFS_CAP_REVISION_2 | VFS_CAP_FLAGS_EFFECTIVE).to_le_bytes());
+    data.extend((caps as u32).to_le_bytes());
+    data.extend(0u32.to_le_bytes());
+    data.extend(((caps >> 32) as u32).to_le_bytes());
+    data.extend(0u32.to_le_bytes());
+
+    lsetxattr(path.as_ref(), CAPABILITY_XATTR, &data, XattrFlags::empty()).with_context(|| {
+        format!(
+            "Failed to set file capabilities on {}",
+            path.as_ref().display()
+        )
+    })?;
+    Ok(())
+}
 
-        if k.trim() == "CONFIG_TMPFS_XATTR" && v.trim() == "y" {
-            return Ok(true);
-        }
-    }
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn set_file_capabilities<P: AsRef<Path>>(_path: P, _caps: u64) -> Result<()> {
+    unimplemented!();
+}
 
-    Ok(false)
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn is_overlay_xattr_supported() -> Result<bool> {
+    Ok(crate::sys::capabilities::probe().tmpfs_xattr)
 }
 
 #[cfg(not(any(target_os = "linux", target_os = "android")))]
@@ -1,3 +1,7 @@
+pub mod apex;
+pub mod capabilities;
 pub mod fs;
 pub mod mount;
+pub mod namespace;
 pub mod nuke;
+pub mod partitions;
@@ -1,10 +1,18 @@
-use std::{path::Path, process::Command};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use anyhow::{Context, Result, bail};
+use anyhow::{Context, Result};
 use procfs::process::Process;
-use rustix::mount::{MountFlags, mount};
+use rustix::mount::{
+    MountFlags, MountPropagationFlags, UnmountFlags, mount, mount_change, unmount,
+};
 
-use crate::sys::fs::ensure_dir_exists;
+use crate::{defs, sys::fs::ensure_dir_exists, utils};
+
+const REPAIR_TIMEOUT: Duration = Duration::from_secs(300);
 
 pub fn detect_mount_source() -> String {
     if ksu::version().is_some() {
@@ -45,16 +53,52 @@ pub fn mount_tmpfs(target: &Path, source: &str) -> Result<()> {
 }
 
 pub fn repair_image(image_path: &Path) -> Result<()> {
-    let status = Command::new("e2fsck")
-        .args(["-y", "-f"])
-        .arg(image_path)
-        .status()
-        .context("Failed to execute e2fsck")?;
-
-    if let Some(code) = status.code()
-        && code > 2
-    {
-        bail!("e2fsck failed with exit code: {}", code);
-    }
+    let outcome = utils::run_e2fsck(
+        [
+            std::ffi::OsStr::new("-y"),
+            std::ffi::OsStr::new("-f"),
+            image_path.as_os_str(),
+        ],
+        REPAIR_TIMEOUT,
+    )
+    .context("Failed to repair image")?;
+
+    log::info!("e2fsck repair of {}: {:?}", image_path.display(), outcome);
     Ok(())
 }
+
+pub struct ScratchMount {
+    path: PathBuf,
+}
+
+impl ScratchMount {
+    pub fn new(name: &str) -> Result<Self> {
+        let path = Path::new(defs::RUN_DIR).join(name);
+
+        if is_mounted(&path) {
+            let _ = unmount(&path, UnmountFlags::DETACH);
+        }
+
+        mount_tmpfs(&path, "tmpfs")
+            .with_context(|| format!("Failed to mount scratch tmpfs at {}", path.display()))?;
+
+        let scratch = Self { path };
+        mount_change(&scratch.path, MountPropagationFlags::PRIVATE)
+            .context("Failed to make scratch mount private")?;
+
+        Ok(scratch)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ScratchMount {
+    fn drop(&mut self) {
+        if let Err(e) = unmount(&self.path, UnmountFlags::DETACH) {
+            log::debug!("Failed to unmount scratch {}: {}", self.path.display(), e);
+        }
+        let _ = fs::remove_dir(&self.path);
+    }
+}
@@ -0,0 +1,206 @@
+use std::{
+    fs, io,
+    os::fd::{AsRawFd, OwnedFd},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use rustix::{
+    fs::CWD,
+    mount::{
+        MountPropagationFlags, MoveMountFlags, OpenTreeFlags, UnmountFlags, mount_bind,
+        mount_change, move_mount, open_tree, unmount,
+    },
+};
+
+use crate::{defs, sys::mount::is_mounted, utils};
+
+pub fn pin_path(module_id: &str) -> Result<PathBuf> {
+    utils::validate_module_id(module_id)?;
+    Ok(Path::new(defs::NAMESPACE_DIR).join(module_id))
+}
+
+pub fn process_path(pid: i32) -> Result<PathBuf> {
+    let path = PathBuf::from(format!("/proc/{}/ns/mnt", pid));
+    if !path.exists() {
+        bail!("Process {} does not exist or has no mount namespace", pid);
+    }
+    Ok(path)
+}
+
+pub fn detach_tree(source: &Path) -> Result<OwnedFd> {
+    open_tree(
+        CWD,
+        source,
+        OpenTreeFlags::OPEN_TREE_CLONE
+            | OpenTreeFlags::OPEN_TREE_CLOEXEC
+            | OpenTreeFlags::AT_RECURSIVE,
+    )
+    .with_context(|| format!("Failed to clone mount tree {}", source.display()))
+}
+
+pub fn attach_tree(tree: &OwnedFd, target: &Path) -> Result<()> {
+    fs::create_dir_all(target)?;
+    move_mount(
+        tree,
+        "",
+        CWD,
+        target,
+        MoveMountFlags::MOVE_MOUNT_F_EMPTY_PATH,
+    )
+    .with_context(|| format!("Failed to attach mount tree at {}", target.display()))
+}
+
+fn prepare_pin_dir(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+
+    if !is_mounted(dir) {
+        mount_bind(dir, dir).context("Failed to bind namespace directory")?;
+    }
+    mount_change(dir, MountPropagationFlags::PRIVATE)
+        .context("Failed to make namespace directory private")?;
+
+    Ok(())
+}
+
+fn unshare_mount_ns() -> Result<i32> {
+    if unsafe { libc::unshare(libc::CLONE_FS | libc::CLONE_NEWNS) } != 0 {
+        bail!("unshare failed: {}", io::Error::last_os_error());
+    }
+    mount_change(
+        "/",
+        MountPropagationFlags::PRIVATE | MountPropagationFlags::REC,
+    )
+    .context("Failed to make namespace root private")?;
+
+    Ok(unsafe { libc::gettid() })
+}
+
+pub fn create(pin: &Path) -> Result<()> {
+    prepare_pin_dir(pin.parent().unwrap_or(Path::new(defs::NAMESPACE_DIR)))?;
+
+    if is_mounted(pin) {
+        let _ = unmount(pin, UnmountFlags::DETACH);
+    }
+    fs::File::create(pin).with_context(|| format!("Failed to create {}", pin.display()))?;
+
+    let (tid_tx, tid_rx) = mpsc::channel();
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+
+    let holder = thread::spawn(move || {
+        let _ = tid_tx.send(unshare_mount_ns());
+        let _ = done_rx.recv();
+    });
+
+    let result = tid_rx
+        .recv()
+        .map_err(|_| anyhow!("namespace holder thread exited early"))
+        .and_then(|tid| tid)
+        .and_then(|tid| {
+            let ns = format!("/proc/self/task/{}/ns/mnt", tid);
+            mount_bind(&ns, pin)
+                .with_context(|| format!("Failed to pin {} at {}", ns, pin.display()))
+        });
+
+    let _ = done_tx.send(());
+    let _ = holder.join();
+
+    result
+}
+
+pub fn enter(pin: &Path) -> Result<()> {
+    let file = fs::File::open(pin)
+        .with_context(|| format!("Failed to open namespace {}", pin.display()))?;
+
+    if unsafe { libc::unshare(libc::CLONE_FS) } != 0 {
+        bail!("unshare(CLONE_FS) failed: {}", io::Error::last_os_error());
+    }
+    if unsafe { libc::setns(file.as_raw_fd(), libc::CLONE_NEWNS) } != 0 {
+        bail!(
+            "setns into {} failed: {}",
+            pin.display(),
+            io::Error::last_os_error()
+        );
+    }
+
+    Ok(())
+}
+
+pub fn run_in<F, R>(pin: &Path, f: F) -> Result<R>
+where
+    F: FnOnce() -> R + Send,
+    R: Send,
+{
+    let threads = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4);
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .context("Failed to build namespace thread pool")?;
+
+    for entered in pool.broadcast(|_| enter(pin)) {
+        entered?;
+    }
+
+    Ok(pool.install(f))
+}
+
+#[cfg(test)]
+mod tests {
+    use rustix::mount::{MountFlags, mount};
+
+    use super::*;
+
+    struct Mounted(PathBuf);
+
+    impl Drop for Mounted {
+        fn drop(&mut self) {
+            let _ = unmount(&self.0, UnmountFlags::DETACH);
+        }
+    }
+
+    fn mount_tmpfs_with(target: &Path, file: &str) -> Result<()> {
+        fs::create_dir_all(target)?;
+        mount("tmpfs", target, c"tmpfs", MountFlags::empty(), None)?;
+        fs::write(target.join(file), "")?;
+        Ok(())
+    }
+
+    #[test]
+    fn isolated_mounts_survive_later_global_mounts() {
+        if !rustix::process::geteuid().is_root() {
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        let target = root.join("target");
+        let pin = dir.path().join("ns/module");
+
+        mount_tmpfs_with(&root, "stock").unwrap();
+        let _root = Mounted(root.clone());
+        mount_change(&root, MountPropagationFlags::SHARED).unwrap();
+        fs::create_dir_all(target.join("etc")).unwrap();
+
+        create(&pin).unwrap();
+        let _pin = Mounted(pin.clone());
+        let _pin_dir = Mounted(dir.path().join("ns"));
+        run_in(&pin, || mount_tmpfs_with(&target.join("etc"), "isolated"))
+            .unwrap()
+            .unwrap();
+
+        mount_tmpfs_with(&target, "global").unwrap();
+        let _global = Mounted(target.clone());
+
+        let visible = run_in(&pin, || {
+            (
+                target.join("etc/isolated").exists(),
+                target.join("global").exists(),
+            )
+        })
+        .unwrap();
+        assert_eq!(visible, (true, false));
+    }
+}
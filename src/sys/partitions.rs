@@ -0,0 +1,149 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use procfs::process::Process;
+use serde::Serialize;
+
+use crate::defs;
+
+const NON_PARTITION_MOUNTS: &[&str] = &[
+    "cache",
+    "data",
+    "metadata",
+    "persist",
+    "efs",
+    "sec_efs",
+    "mnt",
+    "storage",
+    "postinstall",
+    "second_stage_resources",
+    "debug_ramdisk",
+];
+
+static DISCOVERED: OnceLock<Vec<String>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PartitionSource {
+    Builtin,
+    Config,
+    Discovered,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Partition {
+    pub name: String,
+    pub root: PathBuf,
+    pub system_symlink: bool,
+    pub sensitive: bool,
+    pub source: PartitionSource,
+}
+
+impl Partition {
+    fn new(sysroot: &Path, name: &str, source: PartitionSource) -> Self {
+        Self {
+            name: name.to_string(),
+            root: sysroot.join(name),
+            system_symlink: sysroot.join("system").join(name).is_symlink(),
+            sensitive: defs::SENSITIVE_PARTITIONS.contains(&name),
+            source,
+        }
+    }
+
+    fn system_path(&self) -> PathBuf {
+        self.root.with_file_name("system").join(&self.name)
+    }
+
+    pub fn exists(&self) -> bool {
+        self.root.exists() || self.system_path().exists()
+    }
+
+    pub fn is_system(&self) -> bool {
+        self.name == "system"
+    }
+
+    pub fn is_standalone(&self) -> bool {
+        !self.is_system()
+            && self.root.is_dir()
+            && (self.system_symlink || !self.system_path().exists())
+    }
+
+    pub fn mount_root(&self) -> PathBuf {
+        if self.is_system() || self.is_standalone() {
+            self.root.clone()
+        } else {
+            self.system_path()
+        }
+    }
+}
+
+fn scan_mountinfo() -> Vec<String> {
+    let mounts = match Process::myself().and_then(|p| p.mountinfo()) {
+        Ok(mounts) => mounts,
+        Err(e) => {
+            log::warn!("Failed to read mountinfo for partition discovery: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let mut names: Vec<String> = mounts
+        .0
+        .iter()
+        .filter(|m| {
+            m.mount_source
+                .as_deref()
+                .is_some_and(|source| source.starts_with("/dev/block/"))
+        })
+        .filter(|m| m.mount_point.parent() == Some(Path::new("/")))
+        .filter_map(|m| m.mount_point.file_name())
+        .map(|name| name.to_string_lossy().to_string())
+        .filter(|name| !NON_PARTITION_MOUNTS.contains(&name.as_str()))
+        .collect();
+
+    names.sort();
+    names.dedup();
+
+    log::debug!("discovered partitions: {:?}", names);
+
+    names
+}
+
+pub fn discovered() -> &'static [String] {
+    DISCOVERED.get_or_init(scan_mountinfo)
+}
+
+pub fn resolve(extra: &[String]) -> Vec<Partition> {
+    resolve_with(Path::new("/"), extra, discovered())
+}
+
+pub fn resolve_with(sysroot: &Path, extra: &[String], discovered: &[String]) -> Vec<Partition> {
+    let mut partitions: Vec<Partition> = Vec::new();
+
+    let candidates = std::iter::once("system")
+        .chain(defs::BUILTIN_PARTITIONS.iter().copied())
+        .map(|name| (name, PartitionSource::Builtin))
+        .chain(
+            extra
+                .iter()
+                .map(|name| (name.as_str(), PartitionSource::Config)),
+        )
+        .chain(
+            discovered
+                .iter()
+                .map(|name| (name.as_str(), PartitionSource::Discovered)),
+        );
+
+    for (name, source) in candidates {
+        if !partitions.iter().any(|p| p.name == name) {
+            partitions.push(Partition::new(sysroot, name, source));
+        }
+    }
+
+    partitions
+}
+
+pub fn names(extra: &[String]) -> Vec<String> {
+    resolve(extra).into_iter().map(|p| p.name).collect()
+}
@@ -1,9 +1,20 @@
 use std::{
-    ffi::CString,
-    time::{SystemTime, UNIX_EPOCH},
+    ffi::{CString, OsStr},
+    fmt,
+    io::Read,
+    process::{Command, Stdio},
+    sync::{LazyLock, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+static INVOCATIONS: LazyLock<Mutex<Vec<ToolInvocation>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+const STDERR_LOG_LIMIT: usize = 2048;
 
 pub fn camouflage_process(name: &str) -> Result<()> {
     let c_name = CString::new(name)?;
@@ -22,3 +33,296 @@ pub fn random_kworker_name() -> String {
     let y = (nanos >> 4) % 10;
     format!("kworker/u{}:{}", x, y)
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolInvocation {
+    pub argv: Vec<String>,
+    pub duration_ms: u64,
+    pub exit_code: Option<i32>,
+    pub status: String,
+}
+
+#[derive(Debug)]
+pub struct ToolOutput {
+    pub exit_code: i32,
+    pub stderr: String,
+}
+
+#[derive(Debug)]
+pub enum ToolError {
+    Spawn {
+        program: String,
+        source: std::io::Error,
+    },
+    Timeout {
+        program: String,
+        timeout: Duration,
+    },
+    Signaled {
+        program: String,
+    },
+    Failed {
+        program: String,
+        exit_code: i32,
+        stderr: String,
+    },
+    FsckUncorrected {
+        exit_code: i32,
+    },
+    FsckOperational {
+        exit_code: i32,
+    },
+    FsckUsage {
+        exit_code: i32,
+    },
+    FsckCancelled {
+        exit_code: i32,
+    },
+    FsckLibrary {
+        exit_code: i32,
+    },
+}
+
+impl fmt::Display for ToolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Spawn { program, source } => write!(f, "failed to spawn {program}: {source}"),
+            Self::Timeout { program, timeout } => {
+                write!(f, "{program} timed out after {}s", timeout.as_secs())
+            }
+            Self::Signaled { program } => write!(f, "{program} was terminated by a signal"),
+            Self::Failed {
+                program,
+                exit_code,
+                stderr,
+            } => write!(f, "{program} exited with code {exit_code}: {stderr}"),
+            Self::FsckUncorrected { exit_code } => {
+                write!(f, "e2fsck left errors uncorrected (exit {exit_code})")
+            }
+            Self::FsckOperational { exit_code } => {
+                write!(f, "e2fsck operational error (exit {exit_code})")
+            }
+            Self::FsckUsage { exit_code } => {
+                write!(f, "e2fsck usage or syntax error (exit {exit_code})")
+            }
+            Self::FsckCancelled { exit_code } => {
+                write!(f, "e2fsck canceled by user request (exit {exit_code})")
+            }
+            Self::FsckLibrary { exit_code } => {
+                write!(f, "e2fsck shared library error (exit {exit_code})")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ToolError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Spawn { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsckOutcome {
+    Clean,
+    Corrected,
+    CorrectedRebootRequired,
+}
+
+fn record(argv: Vec<String>, started: Instant, exit_code: Option<i32>, status: &str) {
+    let invocation = ToolInvocation {
+        argv,
+        duration_ms: started.elapsed().as_millis() as u64,
+        exit_code,
+        status: status.to_string(),
+    };
+
+    log::debug!(
+        "tool {:?} finished in {}ms: {}",
+        invocation.argv,
+        invocation.duration_ms,
+        invocation.status
+    );
+
+    if let Ok(mut invocations) = INVOCATIONS.lock() {
+        invocations.push(invocation);
+    }
+}
+
+fn truncate_log(text: &str) -> &str {
+    if text.len() <= STDERR_LOG_LIMIT {
+        return text;
+    }
+    let mut end = STDERR_LOG_LIMIT;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+fn spawn_reader<R>(pipe: Option<R>) -> thread::JoinHandle<Vec<u8>>
+where
+    R: Read + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+pub fn run_tool<S, I, A>(program: S, args: I, timeout: Duration) -> Result<ToolOutput, ToolError>
+where
+    S: AsRef<OsStr>,
+    I: IntoIterator<Item = A>,
+    A: AsRef<OsStr>,
+{
+    let program_name = program.as_ref().to_string_lossy().to_string();
+    let mut command = Command::new(program.as_ref());
+    let mut argv = vec![program_name.clone()];
+
+    for arg in args {
+        argv.push(arg.as_ref().to_string_lossy().to_string());
+        command.arg(arg.as_ref());
+    }
+
+    let started = Instant::now();
+
+    let mut child = match command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(source) => {
+            record(argv, started, None, "spawn failed");
+            return Err(ToolError::Spawn {
+                program: program_name,
+                source,
+            });
+        }
+    };
+
+    let stdout_reader = spawn_reader(child.stdout.take());
+    let stderr_reader = spawn_reader(child.stderr.take());
+
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break Some(status),
+            Ok(None) if started.elapsed() >= timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                break None;
+            }
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(e) => {
+                log::warn!("Failed to wait for {}: {}", program_name, e);
+                let _ = child.kill();
+                break child.wait().ok();
+            }
+        }
+    };
+
+    let stdout = String::from_utf8_lossy(&stdout_reader.join().unwrap_or_default()).to_string();
+    let stderr = String::from_utf8_lossy(&stderr_reader.join().unwrap_or_default()).to_string();
+
+    let Some(status) = status else {
+        record(argv, started, None, "timeout");
+        log::warn!(
+            "{} timed out, stderr: {}",
+            program_name,
+            truncate_log(stderr.trim())
+        );
+        return Err(ToolError::Timeout {
+            program: program_name,
+            timeout,
+        });
+    };
+
+    let Some(exit_code) = status.code() else {
+        record(argv, started, None, "signaled");
+        return Err(ToolError::Signaled {
+            program: program_name,
+        });
+    };
+
+    record(argv, started, Some(exit_code), &format!("exit {exit_code}"));
+
+    if !stdout.trim().is_empty() {
+        log::debug!("{} stdout: {}", program_name, truncate_log(stdout.trim()));
+    }
+
+    if !stderr.trim().is_empty() {
+        if exit_code == 0 {
+            log::debug!("{} stderr: {}", program_name, truncate_log(stderr.trim()));
+        } else {
+            log::warn!(
+                "{} exited with {}, stderr: {}",
+                program_name,
+                exit_code,
+                truncate_log(stderr.trim())
+            );
+        }
+    }
+
+    Ok(ToolOutput { exit_code, stderr })
+}
+
+pub fn run_checked<S, I, A>(program: S, args: I, timeout: Duration) -> Result<ToolOutput, ToolError>
+where
+    S: AsRef<OsStr>,
+    I: IntoIterator<Item = A>,
+    A: AsRef<OsStr>,
+{
+    let program_name = program.as_ref().to_string_lossy().to_string();
+    let output = run_tool(program, args, timeout)?;
+
+    if output.exit_code != 0 {
+        return Err(ToolError::Failed {
+            program: program_name,
+            exit_code: output.exit_code,
+            stderr: truncate_log(output.stderr.trim()).to_string(),
+        });
+    }
+
+    Ok(output)
+}
+
+pub fn run_e2fsck<I, A>(args: I, timeout: Duration) -> Result<FsckOutcome, ToolError>
+where
+    I: IntoIterator<Item = A>,
+    A: AsRef<OsStr>,
+{
+    let output = run_tool("e2fsck", args, timeout)?;
+    let exit_code = output.exit_code;
+
+    if exit_code & 128 != 0 {
+        Err(ToolError::FsckLibrary { exit_code })
+    } else if exit_code & 32 != 0 {
+        Err(ToolError::FsckCancelled { exit_code })
+    } else if exit_code & 16 != 0 {
+        Err(ToolError::FsckUsage { exit_code })
+    } else if exit_code & 8 != 0 {
+        Err(ToolError::FsckOperational { exit_code })
+    } else if exit_code & 4 != 0 {
+        Err(ToolError::FsckUncorrected { exit_code })
+    } else if exit_code & 2 != 0 {
+        Ok(FsckOutcome::CorrectedRebootRequired)
+    } else if exit_code & 1 != 0 {
+        Ok(FsckOutcome::Corrected)
+    } else {
+        Ok(FsckOutcome::Clean)
+    }
+}
+
+pub fn take_tool_invocations() -> Vec<ToolInvocation> {
+    INVOCATIONS
+        .lock()
+        .map(|mut invocations| std::mem::take(&mut *invocations))
+        .unwrap_or_default()
+}
@@ -1,5 +1,5 @@
 use std::{
-    path::Path,
+    path::{Path, PathBuf},
     sync::{
         OnceLock,
         atomic::{AtomicBool, Ordering},
@@ -44,3 +44,13 @@ pub fn extract_module_id(path: &Path) -> Option<String> {
         .and_then(|p| p.file_name())
         .map(|s| s.to_string_lossy().to_string())
 }
+
+pub fn split_module_path(path: &Path) -> Option<(String, PathBuf)> {
+    path.ancestors()
+        .find(|p| p.join("module.prop").exists())
+        .and_then(|root| {
+            let id = root.file_name()?.to_string_lossy().to_string();
+            let relative = path.strip_prefix(root).ok()?.to_path_buf();
+            Some((id, relative))
+        })
+}
@@ -168,6 +168,10 @@ const RealAPI: AppAPI = {
         info.zygisksuEnforce = state.zygisksu_enforce ? "1" : "0";
       if (state.tmpfs_xattr_supported !== undefined)
         info.tmpfs_xattr_supported = state.tmpfs_xattr_supported;
+      if (Array.isArray(state.supported_overlay_modes))
+        info.supported_overlay_modes = state.supported_overlay_modes;
+      if (Array.isArray(state.magic_report?.failures))
+        info.magicFailures = state.magic_report.failures;
     }
     return info;
   },
@@ -1,6 +1,7 @@
 export interface ModuleRules {
   default_mode: MountMode;
   paths: Record<string, string>;
+  targets?: Record<string, string>;
 }
 
 export type OverlayMode = "tmpfs" | "ext4" | "erofs";
@@ -34,6 +35,12 @@ export interface StorageStatus {
   error?: string;
 }
 
+export interface MagicMountFailure {
+  path: string;
+  module?: string;
+  error: string;
+}
+
 export interface SystemInfo {
   kernel: string;
   selinux: string;
@@ -42,6 +49,7 @@ export interface SystemInfo {
   zygisksuEnforce?: string;
   supported_overlay_modes?: OverlayMode[];
   tmpfs_xattr_supported?: boolean;
+  magicFailures?: MagicMountFailure[];
 }
 
 export interface DeviceInfo {
@@ -242,6 +242,21 @@ export default function StatusTab() {
               </For>
             </Show>
           </div>
+
+          <Show when={(sysStore.systemInfo?.magicFailures || []).length > 0}>
+            <div class="card-title" style={{ "margin-top": "8px" }}>
+              {uiStore.L?.status?.magicFailures ?? "Magic Mount Failures"}
+            </div>
+
+            <For each={sysStore.systemInfo?.magicFailures}>
+              {(failure) => (
+                <div class="info-row" title={failure.error}>
+                  <span class="info-key">{failure.path}</span>
+                  <span class="info-val">{failure.module || "-"}</span>
+                </div>
+              )}
+            </For>
+          </Show>
         </div>
       </div>
 